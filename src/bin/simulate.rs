@@ -0,0 +1,51 @@
+//! Plays a replay or an input script at full speed, without a window, and
+//! prints how the game ended. Exits with an error when a replay does not
+//! end the way it was recorded.
+//!
+//! ```text
+//! simulate <replay.totr | script>
+//! ```
+
+use std::process::ExitCode;
+
+use totris::replay::{Playback, Replay, Summary};
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: simulate <replay.totr | script>");
+        return ExitCode::FAILURE;
+    };
+    //NOTE: scripts do not know how the game ends, only replays are checked
+    let recorded = path.ends_with(".totr");
+    let replay = match recorded {
+        true => Replay::load(&path).map_err(|error| error.to_string()),
+        false => Replay::load_script(&path).map_err(|error| error.to_string()),
+    };
+    let replay = match replay {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("Invalid replay: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let expected = replay.summary;
+    let mut playback = Playback::new(replay).expect("Invalid ruleset");
+    while !playback.is_over() {
+        playback.step();
+    }
+
+    let game = playback.game();
+    let summary = Summary::of(game);
+    print!("{}", game.engine);
+    println!("score {}", summary.score);
+    println!("lines {}", summary.lines);
+    println!("ticks {}", summary.ticks);
+    println!("hash  {:016x}", summary.checksum);
+
+    if recorded && summary != expected {
+        eprintln!("Mismatch, the replay was recorded as {expected:?}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
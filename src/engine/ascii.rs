@@ -0,0 +1,339 @@
+//! Text representation of boards, for tests, logs and puzzle files.
+//!
+//! Rows are written top to bottom, one character per cell: `.` is empty,
+//! `I O T S Z J L` are locked cells of that tetromino's color, `G` is garbage
+//! and lowercase letters mark the engine's active piece. When parsing,
+//! surrounding whitespace is ignored and missing rows at the top are
+//! treated as empty, so a puzzle only needs to describe its bottom rows.
+//!
+//! Some shapes look the same in several rotations, like the O piece. The
+//! rotation of such an active piece follows its topmost row, as in
+//! `....oo.... E`, so that it survives a round trip. Without it, the first
+//! rotation covering the cells is used.
+
+use std::{fmt, str::FromStr};
+
+use super::{
+    matrix::{Cell, Color},
+    piece::{Piece, Rotation},
+    piece_set::PieceSet,
+    Coordinate, Engine, Matrix, PieceKind,
+};
+
+const EMPTY: char = '.';
+const GARBAGE: char = 'G';
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseBoardError {
+    TooManyRows,
+    InvalidWidth { row: usize },
+    InvalidCell(char),
+    InvalidCursor,
+}
+
+impl fmt::Display for ParseBoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyRows => {
+                write!(f, "more than {} rows", Matrix::HEIGHT)
+            }
+            Self::InvalidWidth { row } => write!(
+                f,
+                "row {} from the bottom is not {} cells wide",
+                row + 1,
+                Matrix::WIDTH
+            ),
+            Self::InvalidCell(c) => write!(f, "invalid cell {c:?}"),
+            Self::InvalidCursor => write!(f, "invalid active piece"),
+        }
+    }
+}
+
+impl std::error::Error for ParseBoardError {}
+
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in (0..Matrix::HEIGHT).rev() {
+            let row = (0..Matrix::WIDTH)
+                .map(|x| cell_char(self[Coordinate::new(x, y)]))
+                .collect::<String>();
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Matrix {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows = match parse_rows(s)? {
+            (rows, None) => rows,
+            (_, Some(_)) => return Err(ParseBoardError::InvalidCursor),
+        };
+        if rows.len() > Matrix::HEIGHT {
+            return Err(ParseBoardError::TooManyRows);
+        }
+
+        let mut matrix = Matrix::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                matrix[Coordinate::new(x, y)] = char_cell(c)?;
+            }
+        }
+        Ok(matrix)
+    }
+}
+
+/// Draws the matrix with the cursor on top of it. Rows above the matrix
+/// are only written when the cursor reaches into them.
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cursor = self.cursor.and_then(|cursor| {
+            Some((cursor.kind.letter().to_ascii_lowercase(), cursor.cells()?))
+        });
+        let top = cursor
+            .as_ref()
+            .and_then(|(_, cells)| cells.iter().map(|coord| coord.y).max());
+        let height =
+            top.map_or(Matrix::HEIGHT, |y| (y + 1).max(Matrix::HEIGHT));
+        //NOTE: only written when the cells alone do not tell
+        let rotation = self.cursor.filter(|cursor| {
+            cursor.cells().is_some_and(|cells| {
+                Piece::covering(cursor.kind, &cells, None)
+                    .is_none_or(|covering| covering.rotation != cursor.rotation)
+            })
+        });
+
+        for y in (0..height).rev() {
+            let row = (0..Matrix::WIDTH)
+                .map(|x| {
+                    let coord = Coordinate::new(x, y);
+                    match &cursor {
+                        Some((c, cells)) if cells.contains(&coord) => *c,
+                        _ if Matrix::on_matrix(coord) => {
+                            cell_char(self.matrix[coord])
+                        }
+                        _ => EMPTY,
+                    }
+                })
+                .collect::<String>();
+            match rotation {
+                Some(cursor) if top == Some(y) => {
+                    writeln!(f, "{row} {}", cursor.rotation.name())?
+                }
+                _ => writeln!(f, "{row}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a board with an optional active piece, see the module
+/// documentation for its rotation.
+impl FromStr for Engine {
+    type Err = ParseBoardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, rotation) = parse_rows(s)?;
+        let mut matrix = Matrix::new();
+        let mut cursor_kind = None;
+        let mut cursor_cells = Vec::new();
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                let coord = Coordinate::new(x, y);
+                if c.is_ascii_lowercase() {
+                    let kind = PieceKind::from_letter(c.to_ascii_uppercase())
+                        .ok_or(ParseBoardError::InvalidCell(c))?;
+                    if cursor_kind.replace(kind).is_some_and(|k| k != kind) {
+                        return Err(ParseBoardError::InvalidCursor);
+                    }
+                    cursor_cells.push(coord);
+                } else if Matrix::on_matrix(coord) {
+                    matrix[coord] = char_cell(c)?;
+                } else if c != EMPTY {
+                    return Err(ParseBoardError::TooManyRows);
+                }
+            }
+        }
+
+        let cursor = match cursor_kind {
+            Some(kind) => Some(
+                Piece::covering(kind, &cursor_cells, rotation)
+                    .ok_or(ParseBoardError::InvalidCursor)?,
+            ),
+            None if rotation.is_some() => {
+                return Err(ParseBoardError::InvalidCursor)
+            }
+            None => None,
+        };
+
+        Ok(Engine {
+            cursor,
+            ..Engine::from_matrix(matrix)
+        })
+    }
+}
+
+fn cell_char(cell: Cell) -> char {
+    match cell {
+        None => EMPTY,
+        Some(Color::Gray) => GARBAGE,
+        Some(color) => PieceSet::TETROMINOES
+            .kinds()
+            .find(|kind| kind.color() == color)
+            .map_or(GARBAGE, |kind| kind.letter()),
+    }
+}
+
+fn char_cell(c: char) -> Result<Cell, ParseBoardError> {
+    match c {
+        EMPTY => Ok(None),
+        GARBAGE => Ok(Some(Color::Gray)),
+        _ => PieceKind::from_letter(c)
+            .map(|kind| Some(kind.color()))
+            .ok_or(ParseBoardError::InvalidCell(c)),
+    }
+}
+
+/// Splits the text into rows of cells, bottom row first, along with the
+/// rotation written after one of them.
+fn parse_rows(
+    s: &str,
+) -> Result<(Vec<Vec<char>>, Option<Rotation>), ParseBoardError> {
+    let mut rotation = None;
+    let mut rows = Vec::new();
+    for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let cells = match line.split_once(char::is_whitespace) {
+            Some((cells, name)) => {
+                let parsed = Rotation::from_name(name.trim())
+                    .ok_or(ParseBoardError::InvalidCursor)?;
+                if rotation.replace(parsed).is_some() {
+                    return Err(ParseBoardError::InvalidCursor);
+                }
+                cells
+            }
+            None => line,
+        };
+        rows.push(cells.chars().collect::<Vec<_>>());
+    }
+    rows.reverse();
+
+    match rows.iter().position(|row| row.len() != Matrix::WIDTH) {
+        Some(row) => Err(ParseBoardError::InvalidWidth { row }),
+        None => Ok((rows, rotation)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::engine::Offset;
+
+    #[test]
+    fn matrix_round_trip() {
+        let matrix: Matrix = "
+            .....T....
+            G.SSTTTZZ.
+            GSSJJJLLZZ
+        "
+        .parse()
+        .expect("Should be a valid board");
+
+        assert_eq!(matrix[Coordinate::new(0, 0)], Some(Color::Gray));
+        assert_eq!(matrix[Coordinate::new(5, 2)], Some(Color::Purple));
+        assert_eq!(matrix[Coordinate::new(9, 1)], None);
+        assert_eq!(matrix.to_string().parse(), Ok(matrix));
+    }
+
+    #[test]
+    fn engine_round_trip() {
+        let board = "
+            ....t.....
+            ...ttt....
+            ..........
+            GGGGGGGG.G
+        ";
+        let engine: Engine = board.parse().expect("Should be a valid board");
+
+        let cursor = engine.cursor.expect("Should have a cursor");
+        assert_eq!(cursor.kind, PieceKind::T);
+        assert_eq!(cursor.rotation, Rotation::N);
+        assert_eq!(cursor.position, Offset::new(4, 2));
+
+        let reparsed: Engine = engine.to_string().parse().unwrap();
+        assert_eq!(reparsed.cursor, engine.cursor);
+        assert_eq!(reparsed.matrix, engine.matrix);
+    }
+
+    #[test]
+    fn cursor_above_matrix() {
+        let mut engine = Engine::new();
+        engine.cursor = Some(Piece::new(PieceKind::I));
+
+        let text = engine.to_string();
+        assert_eq!(text.lines().count(), Matrix::HEIGHT + 1);
+        assert_eq!(text.lines().next(), Some("...iiii..."));
+        assert_eq!(text.parse::<Engine>().unwrap().cursor, engine.cursor);
+    }
+
+    #[rstest]
+    #[case(PieceKind::O, Rotation::E)]
+    #[case(PieceKind::O, Rotation::W)]
+    #[case(PieceKind::I, Rotation::S)]
+    #[case(PieceKind::S, Rotation::W)]
+    #[case(PieceKind::Z, Rotation::S)]
+    fn symmetric_rotation_round_trip(
+        #[case] kind: PieceKind,
+        #[case] rotation: Rotation,
+    ) {
+        let mut engine = Engine::new();
+        engine.cursor = Some(Piece {
+            kind,
+            position: Offset::new(4, 5),
+            rotation,
+            block_size: 1,
+        });
+
+        let text = engine.to_string();
+        assert!(text.lines().any(|line| line.ends_with(rotation.name())));
+        let reparsed: Engine = text.parse().expect("Should be a valid board");
+        assert_eq!(reparsed.cursor, engine.cursor);
+    }
+
+    #[test]
+    fn invalid_boards() {
+        assert_eq!(
+            "..........\n.........".parse::<Matrix>(),
+            Err(ParseBoardError::InvalidWidth { row: 0 })
+        );
+        assert_eq!(
+            ParseBoardError::InvalidWidth { row: 0 }.to_string(),
+            "row 1 from the bottom is not 10 cells wide"
+        );
+        assert_eq!(
+            "....X.....".parse::<Matrix>(),
+            Err(ParseBoardError::InvalidCell('X'))
+        );
+        assert_eq!(
+            "....tt....\n...ss.....".parse::<Engine>().err(),
+            Some(ParseBoardError::InvalidCursor)
+        );
+        assert_eq!(
+            ".tttt.....".parse::<Engine>().err(),
+            Some(ParseBoardError::InvalidCursor)
+        );
+        assert_eq!(
+            "....t..... E\n...ttt....".parse::<Engine>().err(),
+            Some(ParseBoardError::InvalidCursor)
+        );
+        assert_eq!(
+            "....GG.... E".parse::<Matrix>(),
+            Err(ParseBoardError::InvalidCursor)
+        );
+    }
+}
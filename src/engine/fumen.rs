@@ -0,0 +1,864 @@
+//! Encoder and decoder for fumen (v115) strings.
+//!
+//! A fumen is a sequence of pages. Each page stores a 10x23 field plus a
+//! garbage row below the floor, run-length encoded as a diff against the
+//! previous page, followed by the page's piece, flags and an optional
+//! comment. The layout follows knewjade's `tetris-fumen` reference.
+
+use std::{fmt, iter};
+
+use cgmath::Zero;
+
+use super::{
+    matrix::Color,
+    piece::{Kind as PieceKind, Piece, Rotation},
+    Coordinate, Matrix, Offset,
+};
+
+const VERSION_PREFIX: &str = "v115@";
+const QUIZ_PREFIX: &str = "#Q=";
+const FUMEN_KINDS: [PieceKind; 7] = [
+    PieceKind::I,
+    PieceKind::L,
+    PieceKind::O,
+    PieceKind::Z,
+    PieceKind::T,
+    PieceKind::J,
+    PieceKind::S,
+];
+
+const FIELD_WIDTH: usize = Matrix::WIDTH;
+const FIELD_TOP: usize = 23;
+const FIELD_SIZE: usize = (FIELD_TOP + 1) * FIELD_WIDTH;
+
+const MAX_REPEAT: u32 = 63;
+const MAX_COMMENT_LEN: usize = 4095;
+
+const ENCODE_TABLE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const COMMENT_TABLE: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@\
+    ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_TABLE.len() as u32 + 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FumenError {
+    UnsupportedVersion,
+    InvalidCharacter(char),
+    UnexpectedEnd,
+    InvalidField,
+    InvalidPiece,
+    InvalidComment,
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion => write!(f, "not a v115 fumen"),
+            Self::InvalidCharacter(c) => write!(f, "invalid character {c:?}"),
+            Self::UnexpectedEnd => write!(f, "data ends too early"),
+            Self::InvalidField => write!(f, "invalid field"),
+            Self::InvalidPiece => write!(f, "invalid piece"),
+            Self::InvalidComment => write!(f, "invalid comment"),
+        }
+    }
+}
+
+impl std::error::Error for FumenError {}
+
+/// A single fumen page: the board, the piece shown on it and the hold
+/// slot and comment attached to it.
+///
+/// The hold piece is stored in the comment using the fumen quiz syntax
+/// (`#Q=[hold](current)`), since fumen has no dedicated hold field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Page {
+    pub(super) matrix: Matrix,
+    pub(super) piece: Option<Piece>,
+    pub(super) hold: Option<PieceKind>,
+    pub(super) comment: String,
+    pub(super) lock: bool,
+}
+
+impl Page {
+    fn full_comment(&self) -> String {
+        match self.hold {
+            Some(hold) if !self.comment.starts_with(QUIZ_PREFIX) => {
+                let current = self.piece.map(|piece| piece.kind.letter());
+                format!(
+                    "{QUIZ_PREFIX}[{}]({}){}",
+                    hold.letter(),
+                    current.map(String::from).unwrap_or_default(),
+                    self.comment
+                )
+            }
+            _ => self.comment.clone(),
+        }
+    }
+}
+
+pub fn decode(fumen: &str) -> Result<Vec<Page>, FumenError> {
+    let start = fumen
+        .find(VERSION_PREFIX)
+        .ok_or(FumenError::UnsupportedVersion)?;
+    let mut values = Values::parse(&fumen[start + VERSION_PREFIX.len()..])?;
+
+    let mut pages = Vec::new();
+    let mut prev = Field::EMPTY;
+    let mut repeat = 0;
+    let mut comment = String::new();
+
+    while !values.is_empty() {
+        let mut field = prev;
+        if repeat > 0 {
+            repeat -= 1;
+        } else if !field.decode_diff(&mut values)? {
+            repeat = values.poll(1)?;
+        }
+
+        let action = Action::decode(values.poll(3)?)?;
+        if action.comment {
+            comment = decode_comment(&mut values)?;
+        }
+
+        pages.push(Page {
+            matrix: field.to_matrix(),
+            piece: action.piece,
+            hold: quiz_hold(&comment),
+            comment: comment.clone(),
+            lock: action.lock,
+        });
+
+        if action.lock {
+            field.lock(action.piece.as_ref())?;
+            if action.rise {
+                field.rise();
+            }
+            if action.mirror {
+                field.mirror();
+            }
+        }
+        prev = field;
+    }
+
+    Ok(pages)
+}
+
+/// Encodes `pages` into a fumen string.
+/// Pieces that do not fit inside the fumen field are left out.
+pub fn encode(pages: &[Page]) -> String {
+    let mut values = Vec::new();
+    let mut prev = Field::EMPTY;
+    let mut repeat_slot: Option<usize> = None;
+    let mut prev_comment = String::new();
+
+    for (index, page) in pages.iter().enumerate() {
+        let mut field = Field::from_matrix(&page.matrix);
+        let unchanged = field == prev;
+
+        match repeat_slot {
+            Some(slot) if unchanged && values[slot] < MAX_REPEAT => {
+                values[slot] += 1;
+            }
+            _ => {
+                field.encode_diff(&prev, &mut values);
+                repeat_slot = unchanged.then(|| {
+                    values.push(0);
+                    values.len() - 1
+                });
+            }
+        }
+
+        let piece = page.piece.filter(Field::fits);
+        let comment = page.full_comment();
+        let action = Action {
+            piece,
+            rise: false,
+            mirror: false,
+            color: index == 0,
+            comment: comment != prev_comment,
+            lock: page.lock,
+        };
+        push_value(&mut values, action.encode(), 3);
+
+        if action.comment {
+            encode_comment(&comment, &mut values);
+            prev_comment = comment;
+        }
+
+        if action.lock {
+            field
+                .lock(piece.as_ref())
+                .expect("piece was checked to fit");
+        }
+        prev = field;
+    }
+
+    let data = values
+        .into_iter()
+        .map(|value| ENCODE_TABLE[value as usize] as char)
+        .collect::<String>();
+
+    // NOTE: fumen inserts a '?' after the first 42 characters and then
+    //       every 47, so that the string can wrap in old message boards.
+    let (head, tail) = data.split_at(data.len().min(42));
+    let chunks = tail.as_bytes().chunks(47).map(|chunk| {
+        std::str::from_utf8(chunk).expect("fumen data is always ascii")
+    });
+    let body = iter::once(head).chain(chunks).collect::<Vec<_>>().join("?");
+
+    format!("{VERSION_PREFIX}{body}")
+}
+
+struct Values {
+    digits: Vec<u32>,
+    position: usize,
+}
+
+impl Values {
+    fn parse(data: &str) -> Result<Self, FumenError> {
+        let digits = data
+            .chars()
+            .filter(|&c| c != '?')
+            .map(|c| {
+                ENCODE_TABLE
+                    .iter()
+                    .position(|&digit| digit as char == c)
+                    .map(|value| value as u32)
+                    .ok_or(FumenError::InvalidCharacter(c))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            digits,
+            position: 0,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.digits.len()
+    }
+
+    fn poll(&mut self, count: usize) -> Result<u32, FumenError> {
+        let digits = self
+            .digits
+            .get(self.position..self.position + count)
+            .ok_or(FumenError::UnexpectedEnd)?;
+        self.position += count;
+
+        Ok(digits.iter().rev().fold(0, |acc, digit| acc * 64 + digit))
+    }
+}
+
+fn push_value(values: &mut Vec<u32>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        values.push(value % 64);
+        value /= 64;
+    }
+}
+
+/// The fumen field, stored bottom-up. `rows[0]` is the floor row and
+/// `garbage` is the hidden row below it used for rising garbage.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Field {
+    rows: [[u8; FIELD_WIDTH]; FIELD_TOP],
+    garbage: [u8; FIELD_WIDTH],
+}
+
+impl Field {
+    const EMPTY: Self = Self {
+        rows: [[0; FIELD_WIDTH]; FIELD_TOP],
+        garbage: [0; FIELD_WIDTH],
+    };
+
+    fn from_matrix(matrix: &Matrix) -> Self {
+        let mut field = Self::EMPTY;
+        for (y, row) in field.rows.iter_mut().take(Matrix::HEIGHT).enumerate() {
+            for (x, block) in row.iter_mut().enumerate() {
+                *block = matrix[Coordinate::new(x, y)].map_or(0, color_value);
+            }
+        }
+        field
+    }
+
+    fn to_matrix(self) -> Matrix {
+        let mut matrix = Matrix::new();
+        for (y, row) in self.rows.iter().take(Matrix::HEIGHT).enumerate() {
+            for (x, &block) in row.iter().enumerate() {
+                matrix[Coordinate::new(x, y)] = value_color(block);
+            }
+        }
+        matrix
+    }
+
+    /// Blocks in fumen order: top row first, garbage row last.
+    fn blocks(&self) -> [u8; FIELD_SIZE] {
+        let mut blocks = [0; FIELD_SIZE];
+        let rows = self.rows.iter().rev().chain(iter::once(&self.garbage));
+        for (chunk, row) in blocks.chunks_mut(FIELD_WIDTH).zip(rows) {
+            chunk.copy_from_slice(row);
+        }
+        blocks
+    }
+
+    fn set_blocks(&mut self, blocks: &[u8; FIELD_SIZE]) {
+        let rows = self
+            .rows
+            .iter_mut()
+            .rev()
+            .chain(iter::once(&mut self.garbage));
+        for (row, chunk) in rows.zip(blocks.chunks(FIELD_WIDTH)) {
+            row.copy_from_slice(chunk);
+        }
+    }
+
+    /// Applies a run-length encoded diff to the field.
+    /// Returns `false` if the diff left the field unchanged.
+    fn decode_diff(&mut self, values: &mut Values) -> Result<bool, FumenError> {
+        let mut blocks = self.blocks();
+        let mut changed = true;
+        let mut index = 0;
+
+        while index < FIELD_SIZE {
+            let value = values.poll(2)? as usize;
+            let diff = value / FIELD_SIZE;
+            let run = value % FIELD_SIZE + 1;
+            if diff == 8 && run == FIELD_SIZE {
+                changed = false;
+            }
+
+            let run_blocks = blocks
+                .get_mut(index..index + run)
+                .ok_or(FumenError::InvalidField)?;
+            for block in run_blocks {
+                *block = (*block as usize + diff)
+                    .checked_sub(8)
+                    .filter(|&block| block <= 8)
+                    .ok_or(FumenError::InvalidField)?
+                    as u8;
+            }
+            index += run;
+        }
+
+        self.set_blocks(&blocks);
+        Ok(changed)
+    }
+
+    fn encode_diff(&self, prev: &Self, values: &mut Vec<u32>) {
+        let diffs = self
+            .blocks()
+            .iter()
+            .zip(prev.blocks())
+            .map(|(&block, prev)| block as u32 + 8 - prev as u32)
+            .collect::<Vec<_>>();
+
+        let mut index = 0;
+        while index < FIELD_SIZE {
+            let diff = diffs[index];
+            let run = diffs[index..]
+                .iter()
+                .take_while(|&&other| other == diff)
+                .count();
+            push_value(values, diff * FIELD_SIZE as u32 + run as u32 - 1, 2);
+            index += run;
+        }
+    }
+
+    fn fits(piece: &Piece) -> bool {
+        piece.block_size == 1
+            && kind_value(piece.kind).is_some()
+            && Self::piece_blocks(piece).is_some()
+            && Self::location(fumen_center(piece)).is_some()
+    }
+
+    fn piece_blocks(piece: &Piece) -> Option<Vec<(usize, usize)>> {
+        piece
+            .offsets()
+            .into_iter()
+            .map(|offset| {
+                let cell = offset + piece.position;
+                let x = usize::try_from(cell.x).ok()?;
+                let y = usize::try_from(cell.y).ok()?;
+                (x < FIELD_WIDTH && y < FIELD_TOP).then_some((x, y))
+            })
+            .collect()
+    }
+
+    fn location(center: Offset) -> Option<u32> {
+        let x = usize::try_from(center.x).ok()?;
+        let y = usize::try_from(center.y).ok()?;
+        (x < FIELD_WIDTH && y < FIELD_TOP)
+            .then_some((x + (FIELD_TOP - 1 - y) * FIELD_WIDTH) as u32)
+    }
+
+    /// Places `piece` and clears any full lines, like fumen does for
+    /// pages with the lock flag set.
+    fn lock(&mut self, piece: Option<&Piece>) -> Result<(), FumenError> {
+        if let Some(piece) = piece {
+            let value =
+                kind_value(piece.kind).ok_or(FumenError::InvalidPiece)?;
+            for (x, y) in
+                Self::piece_blocks(piece).ok_or(FumenError::InvalidPiece)?
+            {
+                self.rows[y][x] = value;
+            }
+        }
+
+        let mut kept = self
+            .rows
+            .into_iter()
+            .filter(|row| row.contains(&0))
+            .collect::<Vec<_>>();
+        kept.resize(FIELD_TOP, [0; FIELD_WIDTH]);
+        self.rows.copy_from_slice(&kept);
+        Ok(())
+    }
+
+    fn rise(&mut self) {
+        self.rows.copy_within(..FIELD_TOP - 1, 1);
+        self.rows[0] = self.garbage;
+        self.garbage = [0; FIELD_WIDTH];
+    }
+
+    fn mirror(&mut self) {
+        for row in self.rows.iter_mut() {
+            row.reverse();
+        }
+    }
+}
+
+struct Action {
+    piece: Option<Piece>,
+    rise: bool,
+    mirror: bool,
+    color: bool,
+    comment: bool,
+    lock: bool,
+}
+
+impl Action {
+    fn decode(mut value: u32) -> Result<Self, FumenError> {
+        let mut take = |base: u32| {
+            let taken = value % base;
+            value /= base;
+            taken
+        };
+
+        let kind = take(8) as u8;
+        let rotation = take(4);
+        let location = take(FIELD_SIZE as u32) as usize;
+        let rise = take(2) == 1;
+        let mirror = take(2) == 1;
+        let color = take(2) == 1;
+        let comment = take(2) == 1;
+        let lock = take(2) == 0;
+
+        let piece = match kind {
+            0 => None,
+            _ => {
+                let kind = value_kind(kind).ok_or(FumenError::InvalidPiece)?;
+                let rotation = match rotation {
+                    0 => Rotation::S,
+                    1 => Rotation::E,
+                    2 => Rotation::N,
+                    _ => Rotation::W,
+                };
+                //NOTE: the last row of locations is the garbage row, below
+                //      where pieces can be
+                let row = (FIELD_TOP - 1)
+                    .checked_sub(location / FIELD_WIDTH)
+                    .ok_or(FumenError::InvalidPiece)?;
+                let center = Offset::new(
+                    (location % FIELD_WIDTH) as isize,
+                    row as isize,
+                );
+                Some(piece_from_center(kind, rotation, center))
+            }
+        };
+
+        Ok(Self {
+            piece,
+            rise,
+            mirror,
+            color,
+            comment,
+            lock,
+        })
+    }
+
+    fn encode(&self) -> u32 {
+        let (kind, rotation, location) = match self.piece {
+            None => (0, 0, 0),
+            Some(piece) => (
+                kind_value(piece.kind).expect("piece was checked to fit")
+                    as u32,
+                match piece.rotation {
+                    Rotation::S => 0,
+                    Rotation::E => 1,
+                    Rotation::N => 2,
+                    Rotation::W => 3,
+                },
+                Field::location(fumen_center(&piece))
+                    .expect("piece was checked to fit"),
+            ),
+        };
+
+        [self.comment, self.color, self.mirror, self.rise]
+            .into_iter()
+            .fold(u32::from(!self.lock), |acc, flag| acc * 2 + u32::from(flag))
+            * FIELD_SIZE as u32
+            * 4
+            * 8
+            + location * 4 * 8
+            + rotation * 8
+            + kind
+    }
+}
+
+/// Fumen only knows the classic tetrominoes, other kinds have no value.
+fn kind_value(kind: PieceKind) -> Option<u8> {
+    let index = FUMEN_KINDS.iter().position(|&other| other == kind)?;
+    Some(index as u8 + 1)
+}
+
+fn value_kind(value: u8) -> Option<PieceKind> {
+    FUMEN_KINDS.get(value.checked_sub(1)? as usize).copied()
+}
+
+fn color_value(color: Color) -> u8 {
+    FUMEN_KINDS
+        .into_iter()
+        .find(|kind| kind.color() == color)
+        .and_then(kind_value)
+        .unwrap_or(8)
+}
+
+fn value_color(value: u8) -> Option<Color> {
+    match value {
+        0 => None,
+        _ => Some(value_kind(value).map_or(Color::Gray, |kind| kind.color())),
+    }
+}
+
+/// Fumen's own piece shapes, relative to the position it stores. They
+/// rotate around their center like in tetris-fumen, except that fumen
+/// stores the O, I, S and Z rotations covering the same cells at the same
+/// position, so some of their rotations are shifted back onto it.
+fn fumen_offsets(kind: PieceKind, rotation: Rotation) -> Vec<Offset> {
+    #[rustfmt::skip]
+    let cells = match kind.letter() {
+        'I' => [(0, 0), (-1, 0), (1, 0), ( 2, 0)],
+        'T' => [(0, 0), (-1, 0), (1, 0), ( 0, 1)],
+        'O' => [(0, 0), ( 1, 0), (0, 1), ( 1, 1)],
+        'L' => [(0, 0), (-1, 0), (1, 0), ( 1, 1)],
+        'J' => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        'S' => [(0, 0), (-1, 0), (0, 1), ( 1, 1)],
+        'Z' => [(0, 0), ( 1, 0), (0, 1), (-1, 1)],
+        _ => unreachable!("only tetrominoes have a fumen value"),
+    };
+    #[rustfmt::skip]
+    let shift = match (kind.letter(), rotation) {
+        ('O', Rotation::N) => ( 0, -1),
+        ('O', Rotation::S) => ( 1,  0),
+        ('O', Rotation::W) => ( 1, -1),
+        ('I', Rotation::S) => ( 1,  0),
+        ('I', Rotation::W) => ( 0, -1),
+        ('S', Rotation::N) => ( 0, -1),
+        ('S', Rotation::E) => (-1,  0),
+        ('Z', Rotation::N) => ( 0, -1),
+        ('Z', Rotation::W) => ( 1,  0),
+        _ => (0, 0),
+    };
+    cells
+        .map(|cell| Offset::from(cell) * rotation + Offset::from(shift))
+        .into_iter()
+        .collect()
+}
+
+/// The lowest, leftmost cell of a shape. Both shape definitions describe
+/// the same cells, so their anchors line up.
+fn anchor(offsets: &[Offset]) -> Offset {
+    *offsets
+        .iter()
+        .min_by_key(|offset| (offset.y, offset.x))
+        .expect("pieces are never empty")
+}
+
+fn piece_from_center(
+    kind: PieceKind,
+    rotation: Rotation,
+    center: Offset,
+) -> Piece {
+    let piece = Piece {
+        kind,
+        position: Offset::zero(),
+        rotation,
+        block_size: 1,
+    };
+    let fumen_anchor = anchor(&fumen_offsets(kind, rotation));
+    piece.moved_by(center + fumen_anchor - anchor(&piece.offsets()))
+}
+
+fn fumen_center(piece: &Piece) -> Offset {
+    let fumen_anchor = anchor(&fumen_offsets(piece.kind, piece.rotation));
+    piece.position + anchor(&piece.offsets()) - fumen_anchor
+}
+
+fn quiz_hold(comment: &str) -> Option<PieceKind> {
+    let quiz = comment.strip_prefix(QUIZ_PREFIX)?.strip_prefix('[')?;
+    PieceKind::from_letter(quiz.chars().next()?)
+}
+
+fn decode_comment(values: &mut Values) -> Result<String, FumenError> {
+    let len = values.poll(2)? as usize;
+    let mut escaped = String::with_capacity(len);
+
+    while escaped.len() < len {
+        let mut value = values.poll(5)?;
+        for _ in 0..(len - escaped.len()).min(4) {
+            let c = COMMENT_TABLE
+                .get((value % COMMENT_BASE) as usize)
+                .ok_or(FumenError::InvalidComment)?;
+            escaped.push(*c as char);
+            value /= COMMENT_BASE;
+        }
+    }
+
+    unescape(&escaped)
+}
+
+fn encode_comment(comment: &str, values: &mut Vec<u32>) {
+    let mut escaped = escape(comment);
+    escaped.truncate(MAX_COMMENT_LEN);
+    push_value(values, escaped.len() as u32, 2);
+
+    for chunk in escaped.as_bytes().chunks(4) {
+        let value = chunk.iter().rev().fold(0, |acc, &c| {
+            let index = COMMENT_TABLE
+                .iter()
+                .position(|&other| other == c)
+                .expect("escaped comments are printable ascii");
+            acc * COMMENT_BASE + index as u32
+        });
+        push_value(values, value, 5);
+    }
+}
+
+/// Equivalent of javascript's `escape`, which fumen applies to comments.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => {
+                escaped.push(c)
+            }
+            _ if unit < 0x100 => escaped.push_str(&format!("%{unit:02X}")),
+            _ => escaped.push_str(&format!("%u{unit:04X}")),
+        }
+    }
+    escaped
+}
+
+fn unescape(escaped: &str) -> Result<String, FumenError> {
+    let bytes = escaped.as_bytes();
+    let hex = |range: std::ops::Range<usize>| {
+        let digits = escaped.get(range)?;
+        u16::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| digits.chars().all(|c| c.is_ascii_hexdigit()))
+    };
+
+    let mut units = Vec::with_capacity(escaped.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let (unit, len) = match bytes[index] {
+            b'%' if bytes.get(index + 1) == Some(&b'u') => {
+                match hex(index + 2..index + 6) {
+                    Some(unit) => (unit, 6),
+                    None => (b'%' as u16, 1),
+                }
+            }
+            b'%' => match hex(index + 1..index + 3) {
+                Some(unit) => (unit, 3),
+                None => (b'%' as u16, 1),
+            },
+            byte => (byte as u16, 1),
+        };
+        units.push(unit);
+        index += len;
+    }
+
+    String::from_utf16(&units).map_err(|_| FumenError::InvalidComment)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn empty_page() -> Page {
+        Page {
+            matrix: Matrix::new(),
+            piece: None,
+            hold: None,
+            comment: String::new(),
+            lock: true,
+        }
+    }
+
+    #[test]
+    fn empty_field() {
+        assert_eq!(encode(&[empty_page()]), "v115@vhAAgH");
+        assert_eq!(decode("v115@vhAAgH"), Ok(vec![empty_page()]));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert_eq!(decode("v110@7eEAA"), Err(FumenError::UnsupportedVersion));
+    }
+
+    #[test]
+    fn piece_in_the_garbage_row() {
+        assert_eq!(decode("v115@vhAxVn"), Err(FumenError::InvalidPiece));
+        assert_eq!(FumenError::InvalidPiece.to_string(), "invalid piece");
+    }
+
+    #[rstest]
+    #[case(PieceKind::I)]
+    #[case(PieceKind::O)]
+    #[case(PieceKind::T)]
+    #[case(PieceKind::S)]
+    #[case(PieceKind::Z)]
+    #[case(PieceKind::J)]
+    #[case(PieceKind::L)]
+    fn piece_round_trip(#[case] kind: PieceKind) {
+        for rotation in [Rotation::N, Rotation::E, Rotation::S, Rotation::W] {
+            let piece = Piece {
+                kind,
+                position: Offset::new(4, 10),
+                rotation,
+                block_size: 1,
+            };
+            let page = Page {
+                piece: Some(piece),
+                lock: false,
+                ..empty_page()
+            };
+
+            assert_eq!(
+                decode(&encode(std::slice::from_ref(&page))),
+                Ok(vec![page])
+            );
+        }
+    }
+
+    /// Single unlocked pieces on an empty field, as tetris-fumen encodes
+    /// them, with the cells they cover.
+    #[rstest]
+    #[case("v115@vhARGn", 'I', "N", &[(3, 2), (4, 2), (5, 2), (6, 2)])]
+    #[case("v115@vhAJGn", 'I', "E", &[(4, 0), (4, 1), (4, 2), (4, 3)])]
+    #[case("v115@vhAhFn", 'I', "S", &[(2, 2), (3, 2), (4, 2), (5, 2)])]
+    #[case("v115@vhAZBn", 'I', "W", &[(4, 1), (4, 2), (4, 3), (4, 4)])]
+    #[case("v115@vhATBn", 'O', "N", &[(4, 2), (4, 3), (5, 2), (5, 3)])]
+    #[case("v115@vhALGn", 'O', "E", &[(4, 1), (4, 2), (5, 1), (5, 2)])]
+    #[case("v115@vhAjFn", 'O', "S", &[(3, 1), (3, 2), (4, 1), (4, 2)])]
+    #[case("v115@vhA7An", 'O', "W", &[(3, 2), (3, 3), (4, 2), (4, 3)])]
+    #[case("v115@vhAVGn", 'T', "N", &[(3, 2), (4, 2), (4, 3), (5, 2)])]
+    #[case("v115@vhANGn", 'T', "E", &[(4, 1), (4, 2), (4, 3), (5, 2)])]
+    #[case("v115@vhAFGn", 'T', "S", &[(3, 2), (4, 1), (4, 2), (5, 2)])]
+    #[case("v115@vhAdGn", 'T', "W", &[(3, 2), (4, 1), (4, 2), (4, 3)])]
+    #[case("v115@vhAXBn", 'S', "N", &[(3, 2), (4, 2), (4, 3), (5, 3)])]
+    #[case("v115@vhAvGn", 'S', "E", &[(4, 2), (4, 3), (5, 1), (5, 2)])]
+    #[case("v115@vhAHGn", 'S', "S", &[(3, 1), (4, 1), (4, 2), (5, 2)])]
+    #[case("v115@vhAfGn", 'S', "W", &[(3, 2), (3, 3), (4, 1), (4, 2)])]
+    #[case("v115@vhAUBn", 'Z', "N", &[(3, 3), (4, 2), (4, 3), (5, 2)])]
+    #[case("v115@vhAMGn", 'Z', "E", &[(4, 1), (4, 2), (5, 2), (5, 3)])]
+    #[case("v115@vhAEGn", 'Z', "S", &[(3, 2), (4, 1), (4, 2), (5, 1)])]
+    #[case("v115@vhA8Fn", 'Z', "W", &[(3, 1), (3, 2), (4, 2), (4, 3)])]
+    #[case("v115@vhAWGn", 'J', "N", &[(3, 2), (3, 3), (4, 2), (5, 2)])]
+    #[case("v115@vhAOGn", 'J', "E", &[(4, 1), (4, 2), (4, 3), (5, 3)])]
+    #[case("v115@vhAGGn", 'J', "S", &[(3, 2), (4, 2), (5, 1), (5, 2)])]
+    #[case("v115@vhAeGn", 'J', "W", &[(3, 1), (4, 1), (4, 2), (4, 3)])]
+    #[case("v115@vhASGn", 'L', "N", &[(3, 2), (4, 2), (5, 2), (5, 3)])]
+    #[case("v115@vhAKGn", 'L', "E", &[(4, 1), (4, 2), (4, 3), (5, 1)])]
+    #[case("v115@vhACGn", 'L', "S", &[(3, 1), (3, 2), (4, 2), (5, 2)])]
+    #[case("v115@vhAaGn", 'L', "W", &[(3, 3), (4, 1), (4, 2), (4, 3)])]
+    fn reference_pieces(
+        #[case] fumen: &str,
+        #[case] letter: char,
+        #[case] rotation: &str,
+        #[case] cells: &[(usize, usize)],
+    ) {
+        let kind = PieceKind::from_letter(letter).unwrap();
+        let rotation = Rotation::from_name(rotation).unwrap();
+        let cells = cells
+            .iter()
+            .map(|&(x, y)| Coordinate::new(x, y))
+            .collect::<Vec<_>>();
+        let page = Page {
+            piece: Piece::covering(kind, &cells, Some(rotation)),
+            lock: false,
+            ..empty_page()
+        };
+        assert!(page.piece.is_some());
+
+        assert_eq!(encode(std::slice::from_ref(&page)), fumen);
+        assert_eq!(decode(fumen), Ok(vec![page]));
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        let mut matrix = Matrix::new();
+        for x in 0..Matrix::WIDTH - 1 {
+            matrix[Coordinate::new(x, 0)] = Some(Color::Gray);
+        }
+        matrix[Coordinate::new(3, 1)] = Some(Color::Purple);
+
+        let first = Page {
+            matrix: matrix.clone(),
+            piece: Some(Piece {
+                kind: PieceKind::I,
+                position: Offset::new(9, 2),
+                rotation: Rotation::W,
+                block_size: 1,
+            }),
+            hold: Some(PieceKind::T),
+            comment: "hello, fumen! ✓".to_string(),
+            lock: true,
+        };
+
+        // The I piece completes the bottom row
+        matrix.clear_lines(&[0]);
+        for y in 0..3 {
+            matrix[Coordinate::new(9, y)] = Some(Color::Cyan);
+        }
+        let second = Page {
+            matrix,
+            comment: "#Q=[T](I)hello, fumen! ✓".to_string(),
+            ..first.clone()
+        };
+        let third = Page {
+            piece: None,
+            ..second.clone()
+        };
+
+        let decoded = decode(&encode(&[first, second.clone(), third]))
+            .expect("Should be a valid fumen");
+
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[0].comment, "#Q=[T](I)hello, fumen! ✓");
+        assert_eq!(decoded[0].hold, Some(PieceKind::T));
+        assert_eq!(decoded[1], second);
+        assert!(decoded[2].piece.is_none());
+        assert_eq!(decoded[2].matrix, second.matrix);
+    }
+
+    #[test]
+    fn wrapped_fumen() {
+        let pages = vec![empty_page(); 100];
+        let fumen = encode(&pages);
+        assert!(fumen.contains('?'));
+        assert_eq!(decode(&fumen), Ok(pages));
+    }
+}
@@ -4,10 +4,6 @@ pub trait GridIncrement: Sized {
     type Width: Into<usize>;
     const WIDTH: Self::Width;
 
-    fn grid_incd(mut self) -> Self {
-        self.grid_inc();
-        self
-    }
     fn grid_inc(&mut self);
 }
 
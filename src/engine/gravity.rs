@@ -0,0 +1,324 @@
+//! Gravity, in rows per frame like most games specify it, and the curves
+//! giving it for each level.
+//!
+//! Besides the built-in curves, a curve can be loaded from a table with
+//! one step per line, applying from its level until the next step:
+//!
+//! ```text
+//! # level  rows per frame
+//! 0        1/60
+//! 10       0.5
+//! 20       20
+//! ```
+
+use std::{
+    collections::HashMap, fmt, str::FromStr, sync::Mutex, time::Duration,
+};
+
+use once_cell::sync::Lazy;
+
+use super::Matrix;
+
+/// Rows a piece falls each frame, at [`Gravity::FRAMES_PER_SECOND`].
+/// Fractions build up over several frames, and anything from
+/// [`Gravity::MAX`] up drops the piece to the floor right away.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Gravity(pub f32);
+
+impl Gravity {
+    pub const FRAMES_PER_SECOND: f32 = 60.0;
+    /// 20G: the piece crosses the whole matrix in a single frame.
+    pub const MAX: Self = Self(Matrix::HEIGHT as f32);
+
+    /// Gravity moving one row every `drop_time`.
+    pub fn from_drop_time(drop_time: Duration) -> Self {
+        Self((Self::FRAMES_PER_SECOND * drop_time.as_secs_f32()).recip())
+    }
+
+    pub fn is_instant(&self) -> bool {
+        *self >= Self::MAX
+    }
+
+    /// Gravity `factor` times as strong, as when soft dropping.
+    pub fn boosted(&self, factor: f32) -> Self {
+        Self(self.0 * factor)
+    }
+
+    /// Rows fallen over `elapsed`, fractions included.
+    pub fn rows(&self, elapsed: Duration) -> f32 {
+        self.0 * elapsed.as_secs_f32() * Self::FRAMES_PER_SECOND
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GravityCurve {
+    /// `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds per row, until
+    /// the base reaches 0 past level 115 and pieces drop at 20G.
+    Guideline,
+    /// Steps of `(level, gravity)`, sorted by level. Each applies from
+    /// its level until the next one.
+    Table(&'static [(u32, Gravity)]),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseGravityCurveError {
+    Empty,
+    InvalidLine { line: usize },
+    UnsortedLevel { line: usize },
+}
+
+impl fmt::Display for ParseGravityCurveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no steps defined"),
+            Self::InvalidLine { line } => {
+                write!(f, "invalid step on line {}", line + 1)
+            }
+            Self::UnsortedLevel { line } => {
+                write!(f, "level out of order on line {}", line + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseGravityCurveError {}
+
+const fn frames_per_row(frames: f32) -> Gravity {
+    Gravity(1.0 / frames)
+}
+
+const fn internal(value: f32) -> Gravity {
+    Gravity(value / 256.0)
+}
+
+#[rustfmt::skip]
+static NES_TABLE: [(u32, Gravity); 15] = [
+    (0, frames_per_row(48.0)), (1, frames_per_row(43.0)),
+    (2, frames_per_row(38.0)), (3, frames_per_row(33.0)),
+    (4, frames_per_row(28.0)), (5, frames_per_row(23.0)),
+    (6, frames_per_row(18.0)), (7, frames_per_row(13.0)),
+    (8, frames_per_row(8.0)), (9, frames_per_row(6.0)),
+    (10, frames_per_row(5.0)), (13, frames_per_row(4.0)),
+    (16, frames_per_row(3.0)), (19, frames_per_row(2.0)),
+    (29, frames_per_row(1.0)),
+];
+
+/// TGM internal gravity, in 256ths of a row per frame.
+#[rustfmt::skip]
+static TGM_TABLE: [(u32, Gravity); 30] = [
+    (0, internal(4.0)), (30, internal(6.0)), (35, internal(8.0)),
+    (40, internal(10.0)), (50, internal(12.0)), (60, internal(16.0)),
+    (70, internal(32.0)), (80, internal(48.0)), (90, internal(64.0)),
+    (100, internal(80.0)), (120, internal(96.0)), (140, internal(112.0)),
+    (160, internal(128.0)), (170, internal(144.0)), (200, internal(4.0)),
+    (220, internal(32.0)), (230, internal(64.0)), (233, internal(96.0)),
+    (236, internal(128.0)), (239, internal(160.0)), (243, internal(192.0)),
+    (247, internal(224.0)), (251, internal(256.0)), (300, internal(512.0)),
+    (330, internal(768.0)), (360, internal(1024.0)), (400, internal(1280.0)),
+    (420, internal(1024.0)), (450, internal(768.0)), (500, internal(5120.0)),
+];
+
+type Steps = &'static [(u32, Gravity)];
+
+/// Tables parsed by [`GravityCurve::interned`], by their definition.
+static PARSED_TABLES: Lazy<Mutex<HashMap<String, Steps>>> =
+    Lazy::new(Default::default);
+
+impl GravityCurve {
+    pub const NES: Self = Self::Table(&NES_TABLE);
+    pub const TGM: Self = Self::Table(&TGM_TABLE);
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "guideline" => Some(Self::Guideline),
+            "nes" => Some(Self::NES),
+            "tgm" => Some(Self::TGM),
+            _ => None,
+        }
+    }
+
+    /// Parses a gravity table once per definition, the same text always
+    /// gives the same steps back. Parsed steps are leaked, like built-in
+    /// tables they live for the rest of the program.
+    pub fn interned(text: &str) -> Result<Self, ParseGravityCurveError> {
+        let mut tables = PARSED_TABLES.lock().unwrap();
+        if let Some(steps) = tables.get(text) {
+            return Ok(Self::Table(steps));
+        }
+        let steps: Steps = parse_steps(text)?.leak();
+        tables.insert(text.to_owned(), steps);
+        Ok(Self::Table(steps))
+    }
+
+    pub fn gravity(&self, level: u32) -> Gravity {
+        match self {
+            Self::Guideline => {
+                let level = level.saturating_sub(1);
+                let base = 0.8 - (level as f32 * 0.007);
+                match base > 0.0 {
+                    true => Gravity::from_drop_time(Duration::from_secs_f32(
+                        base.powi(level as _),
+                    )),
+                    false => Gravity::MAX,
+                }
+            }
+            Self::Table(steps) => steps
+                .iter()
+                .take_while(|(from, _)| *from <= level)
+                .last()
+                .or(steps.first())
+                .map_or(Gravity(0.0), |&(_, gravity)| gravity),
+        }
+    }
+}
+
+/// Parses a gravity table, see [`GravityCurve::interned`].
+impl FromStr for GravityCurve {
+    type Err = ParseGravityCurveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::interned(s)
+    }
+}
+
+fn parse_steps(s: &str) -> Result<Vec<(u32, Gravity)>, ParseGravityCurveError> {
+    let mut steps: Vec<(u32, Gravity)> = Vec::new();
+
+    for (index, line) in s.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let step = parse_step(line)
+            .ok_or(ParseGravityCurveError::InvalidLine { line: index })?;
+        if steps.last().is_some_and(|(level, _)| *level >= step.0) {
+            return Err(ParseGravityCurveError::UnsortedLevel { line: index });
+        }
+        steps.push(step);
+    }
+
+    if steps.is_empty() {
+        return Err(ParseGravityCurveError::Empty);
+    }
+    Ok(steps)
+}
+
+fn parse_step(line: &str) -> Option<(u32, Gravity)> {
+    let mut values = line.split_whitespace();
+    let (Some(level), Some(gravity), None) =
+        (values.next(), values.next(), values.next())
+    else {
+        return None;
+    };
+
+    let gravity = match gravity.split_once('/') {
+        Some((rows, frames)) => {
+            rows.parse::<f32>().ok()? / frames.parse::<f32>().ok()?
+        }
+        None => gravity.parse().ok()?,
+    };
+    (gravity.is_finite() && gravity >= 0.0)
+        .then_some((level.parse().ok()?, Gravity(gravity)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Duration::from_secs(1), 1.0 / 60.0)]
+    #[case(Duration::from_millis(500), 1.0 / 30.0)]
+    #[case(Duration::from_secs_f32(1.0 / 120.0), 2.0)]
+    fn from_drop_time(#[case] drop_time: Duration, #[case] expected: f32) {
+        let gravity = Gravity::from_drop_time(drop_time);
+        assert!((gravity.0 - expected).abs() < 1e-4, "{gravity:?}");
+    }
+
+    #[test]
+    fn rows_per_frame() {
+        let frame = Duration::from_secs_f32(1.0 / Gravity::FRAMES_PER_SECOND);
+        assert!((Gravity(0.5).rows(frame * 3) - 1.5).abs() < 1e-4);
+        assert!((Gravity(3.0).rows(frame) - 3.0).abs() < 1e-4);
+        assert!(!Gravity(3.0).is_instant());
+        assert!(Gravity(1.0).boosted(20.0).is_instant());
+    }
+
+    #[rstest]
+    #[case(GravityCurve::Guideline, 1, 1.0 / 60.0)]
+    #[case(GravityCurve::NES, 0, 1.0 / 48.0)]
+    #[case(GravityCurve::NES, 12, 1.0 / 5.0)]
+    #[case(GravityCurve::NES, 100, 1.0)]
+    #[case(GravityCurve::TGM, 0, 4.0 / 256.0)]
+    #[case(GravityCurve::TGM, 205, 4.0 / 256.0)]
+    #[case(GravityCurve::TGM, 999, 20.0)]
+    fn curves(
+        #[case] curve: GravityCurve,
+        #[case] level: u32,
+        #[case] expected: f32,
+    ) {
+        let gravity = curve.gravity(level);
+        assert!((gravity.0 - expected).abs() < 1e-4, "{gravity:?}");
+    }
+
+    #[rstest]
+    #[case(15, false)]
+    #[case(20, true)]
+    #[case(115, true)]
+    #[case(116, true)]
+    #[case(232, true)]
+    #[case(u32::MAX, true)]
+    fn guideline_reaches_twenty_g(#[case] level: u32, #[case] instant: bool) {
+        let gravity = GravityCurve::Guideline.gravity(level);
+        assert_eq!(gravity.is_instant(), instant, "{gravity:?}");
+    }
+
+    #[test]
+    fn parse_table() {
+        let curve: GravityCurve = "
+            # level  rows per frame
+            1        1/60
+            10       0.5   # halfway
+            20       20
+        "
+        .parse()
+        .expect("Should be a valid table");
+
+        assert_eq!(curve.gravity(0), Gravity(1.0 / 60.0));
+        assert_eq!(curve.gravity(15), Gravity(0.5));
+        assert!(curve.gravity(20).is_instant());
+    }
+
+    #[test]
+    fn tables_are_parsed_once() {
+        let table = "0 1/60\n10 0.5";
+        let (Ok(GravityCurve::Table(first)), Ok(GravityCurve::Table(second))) =
+            (GravityCurve::interned(table), table.parse())
+        else {
+            panic!("Should be valid tables");
+        };
+        assert!(std::ptr::eq(first, second));
+    }
+
+    #[test]
+    fn invalid_tables() {
+        assert_eq!(
+            "# nothing".parse::<GravityCurve>(),
+            Err(ParseGravityCurveError::Empty)
+        );
+        assert_eq!(
+            "0 1/60\n5".parse::<GravityCurve>(),
+            Err(ParseGravityCurveError::InvalidLine { line: 1 })
+        );
+        assert_eq!(
+            "0 fast".parse::<GravityCurve>(),
+            Err(ParseGravityCurveError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "10 1\n5 2".parse::<GravityCurve>(),
+            Err(ParseGravityCurveError::UnsortedLevel { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Hold rules: how many pieces can be held and how often.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HoldPolicy {
+    /// Classic games, the hold input does nothing.
+    Disabled,
+    /// One slot, swapped at most once per piece.
+    #[default]
+    Standard,
+    /// One slot, swapped as often as wanted. Meant for training.
+    Unlimited,
+    /// A queue of slots, swapped at most once per piece. Holding fills the
+    /// queue first, then swaps with its front, which the cycle input picks.
+    Queue(usize),
+}
+
+impl HoldPolicy {
+    /// Looks up a policy by name, `none`, `standard` or `unlimited`, or a
+    /// number of slots for a hold queue.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::Disabled),
+            "standard" => Some(Self::Standard),
+            "unlimited" => Some(Self::Unlimited),
+            slots => match slots.parse().ok()? {
+                0 => Some(Self::Disabled),
+                slots => Some(Self::Queue(slots)),
+            },
+        }
+    }
+
+    pub fn slots(&self) -> usize {
+        match *self {
+            Self::Disabled => 0,
+            Self::Standard | Self::Unlimited => 1,
+            Self::Queue(slots) => slots,
+        }
+    }
+
+    fn once_per_piece(&self) -> bool {
+        !matches!(self, Self::Unlimited)
+    }
+
+    /// Whether a hold is allowed, given if one was made for this piece.
+    pub(super) fn check(&self, used: bool) -> Result<(), HoldError> {
+        if self.slots() == 0 {
+            Err(HoldError::Disabled)
+        } else if used && self.once_per_piece() {
+            Err(HoldError::AlreadyUsed)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldError {
+    NoCursor,
+    Disabled,
+    AlreadyUsed,
+    NothingHeld,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::engine::Engine;
+
+    fn engine(policy: HoldPolicy) -> Engine {
+        let mut engine = Engine::new().with_hold_policy(policy);
+        engine.add_cursor();
+        engine
+    }
+
+    #[rstest]
+    #[case("none", Some(HoldPolicy::Disabled))]
+    #[case("standard", Some(HoldPolicy::Standard))]
+    #[case("unlimited", Some(HoldPolicy::Unlimited))]
+    #[case("3", Some(HoldPolicy::Queue(3)))]
+    #[case("0", Some(HoldPolicy::Disabled))]
+    #[case("many", None)]
+    fn from_name(#[case] name: &str, #[case] expected: Option<HoldPolicy>) {
+        assert_eq!(HoldPolicy::from_name(name), expected);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut engine = engine(HoldPolicy::Disabled);
+        assert_eq!(engine.hold_cursor(), Err(HoldError::Disabled));
+        assert!(engine.held_cursor_info().is_empty());
+    }
+
+    #[test]
+    fn no_cursor() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.hold_cursor(), Err(HoldError::NoCursor));
+    }
+
+    #[test]
+    fn standard_once_per_piece() {
+        let mut engine = engine(HoldPolicy::Standard);
+        let first = engine.cursor.unwrap().kind;
+
+        assert_eq!(engine.hold_cursor(), Ok(()));
+        assert_eq!(engine.held[0].kind, first);
+        assert_eq!(engine.hold_cursor(), Err(HoldError::AlreadyUsed));
+
+        engine.hard_drop();
+        engine.place_cursor();
+        engine.add_cursor();
+        assert_eq!(engine.hold_cursor(), Ok(()));
+        assert_eq!(engine.cursor.unwrap().kind, first);
+    }
+
+    #[test]
+    fn unlimited_swaps_back_and_forth() {
+        let mut engine = engine(HoldPolicy::Unlimited);
+        let first = engine.cursor.unwrap().kind;
+
+        assert_eq!(engine.hold_cursor(), Ok(()));
+        let second = engine.cursor.unwrap().kind;
+        assert_eq!(engine.hold_cursor(), Ok(()));
+        assert_eq!(engine.cursor.unwrap().kind, first);
+        assert_eq!(engine.held[0].kind, second);
+    }
+
+    #[test]
+    fn queue_fills_then_cycles() {
+        let mut engine = engine(HoldPolicy::Queue(2));
+        let mut held = Vec::new();
+        for _ in 0..2 {
+            held.push(engine.cursor.unwrap().kind);
+            assert_eq!(engine.hold_cursor(), Ok(()));
+            engine.hard_drop();
+            engine.place_cursor();
+            engine.add_cursor();
+        }
+        assert_eq!(engine.held_cursor_info().len(), 2);
+
+        assert_eq!(engine.cycle_hold(), Ok(()));
+        assert_eq!(engine.hold_cursor(), Ok(()));
+        assert_eq!(engine.cursor.unwrap().kind, held[1]);
+        assert_eq!(engine.held[1].kind, held[0]);
+    }
+}
@@ -1,9 +1,12 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{borrow::Cow, collections::HashMap, sync::Mutex};
 
 use cgmath::Zero;
 use once_cell::sync::Lazy;
+use strum::IntoEnumIterator;
 
-use super::{piece::Rotation, Offset, PieceKind, RotateKind};
+use super::{
+    piece::Rotation, piece_set::KickTable, Offset, PieceKind, RotateKind,
+};
 
 pub trait Kick {
     fn offset(&self) -> Offset;
@@ -128,13 +131,32 @@ impl SrsPlus {
     }
 
     pub fn get_kicks(&self) -> Vec<Offset> {
-        match self.piece_kind {
-            PieceKind::O => vec![Offset::zero()],
-            PieceKind::I => self.get_i_kicks(),
-            _ => self.get_std_kicks(),
+        match self.piece_kind.kick_table() {
+            KickTable::None => vec![Offset::zero()],
+            KickTable::SrsPlus => self.get_std_kicks(),
+            KickTable::SrsPlusI => self.get_i_kicks(),
+            KickTable::Custom(table) => self.get_custom_kicks(table),
         }
     }
 
+    fn get_custom_kicks(
+        &self,
+        table: &[Cow<'static, [Offset]>; 4],
+    ) -> Vec<Offset> {
+        let mut modifier = 1;
+        let mut rotation = self.rotation;
+
+        if let RotateKind::CounterClockwise = self.rotate_kind {
+            modifier = -1;
+            rotation = rotation + self.rotate_kind;
+        }
+
+        let index = Rotation::iter()
+            .position(|other| other == rotation)
+            .expect("invalid rotation");
+        table[index].iter().map(|kick| *kick * modifier).collect()
+    }
+
     fn get_std_kicks(&self) -> Vec<Offset> {
         let mut modifier = 1;
         let mut rotation = self.rotation;
@@ -168,18 +190,18 @@ impl SrsPlus {
 
 #[cfg(test)]
 mod test {
-    use super::{PieceKind::*, RotateKind::*, Rotation::*, *};
+    use super::{RotateKind::*, Rotation::*, *};
     use rstest::rstest;
 
     #[rstest]
-    #[case(SrsPlus::new(J, N, Clockwise),
+    #[case(SrsPlus::new(PieceKind::J, N, Clockwise),
             vec![
                 Offset::new(-1, 0),
                 Offset::new(-1, 1),
                 Offset::new(0, -2),
                 Offset::new(-1, -2),
             ])]
-    #[case(SrsPlus::new(J, E, CounterClockwise),
+    #[case(SrsPlus::new(PieceKind::J, E, CounterClockwise),
             vec![
                 Offset::new(1, 0),
                 Offset::new(1, -1),
@@ -1,15 +1,14 @@
-use std::{
-    ops::{Index, IndexMut},
-    slice::ArrayChunks,
-};
+use std::ops::{Index, IndexMut};
 
 use super::{geometry::GridIncrement, piece::Piece, Coordinate};
 
 #[rustfmt::skip]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum Color { Yellow, Cyan, Purple, Orange, Blue, Green, Red, }
+pub enum Color { Yellow, Cyan, Purple, Orange, Blue, Green, Red, Gray }
 
-type Cell = Option<Color>;
+pub type Cell = Option<Color>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Matrix(pub(super) [Cell; Matrix::SIZE]);
 
 impl Matrix {
@@ -17,7 +16,7 @@ impl Matrix {
     pub const HEIGHT: usize = 20;
     pub const SIZE: usize = Self::WIDTH * Self::HEIGHT;
 
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self([None; Self::SIZE])
     }
 
@@ -48,31 +47,42 @@ impl Matrix {
         coord.x < Self::WIDTH
     }
 
-    pub(super) fn full_lines(&self) -> Vec<usize> {
-        self.lines()
+    /// Returns the full lines, only counting lines whose whole row of
+    /// `block_size` tall blocks is full.
+    pub(super) fn full_lines(&self, block_size: usize) -> Vec<usize> {
+        let full: Vec<usize> = self
+            .lines()
             .enumerate()
             .filter_map(|(i, line)| {
                 line.iter().all(Option::is_some).then_some(i)
             })
+            .collect();
+
+        full.iter()
+            .copied()
+            .filter(|line| {
+                let first = line - line % block_size;
+                (first..first + block_size).all(|line| full.contains(&line))
+            })
             .collect()
     }
 
     pub(super) fn clear_lines(&mut self, indices: &[usize]) {
-        debug_assert!(indices.is_sorted());
-        for &line in indices.iter().rev() {
-            // override the line to clear with the remainder of the matrix
-            let start_of_remainder = Self::WIDTH * (line + 1);
-            self.0.copy_within(start_of_remainder.., line * Self::WIDTH);
-            self.0[Self::SIZE - Self::WIDTH..].fill(None);
-        }
+        clear_rows(&mut self.0, indices, None);
     }
 
-    fn indexing(Coordinate { x, y }: Coordinate) -> usize {
+    pub(super) fn indexing(Coordinate { x, y }: Coordinate) -> usize {
         x + y * Self::WIDTH
     }
 
-    fn lines(&self) -> ArrayChunks<'_, Cell, { Self::WIDTH }> {
-        self.0.array_chunks()
+    fn lines(&self) -> std::slice::Iter<'_, [Cell; Self::WIDTH]> {
+        self.0.as_chunks().0.iter()
+    }
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -92,6 +102,24 @@ impl IndexMut<Coordinate> for Matrix {
     }
 }
 
+/// Removes the rows at `indices` from a row major buffer shaped like the
+/// matrix, shifting the rows above down and filling the top with `empty`.
+/// Used for the matrix itself and for data kept per cell alongside it.
+pub(super) fn clear_rows<T: Copy>(
+    cells: &mut [T],
+    indices: &[usize],
+    empty: T,
+) {
+    debug_assert!(indices.is_sorted());
+    debug_assert_eq!(cells.len(), Matrix::SIZE);
+    for &line in indices.iter().rev() {
+        // override the line to clear with the remainder of the matrix
+        let start_of_remainder = Matrix::WIDTH * (line + 1);
+        cells.copy_within(start_of_remainder.., line * Matrix::WIDTH);
+        cells[Matrix::SIZE - Matrix::WIDTH..].fill(empty);
+    }
+}
+
 pub struct CellIter<'matrix> {
     pub(super) position: Coordinate,
     pub(super) cells: std::slice::Iter<'matrix, Cell>,
@@ -115,11 +143,29 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn full_lines_in_blocks() {
+        let matrix: Matrix = "
+            GGGGGGGGGG
+            GGGGGGGGGG
+            GGGGGGGGGG
+            GGGG.GGGGG
+        "
+        .parse()
+        .expect("Should be a valid board");
+
+        assert_eq!(matrix.full_lines(1), [1, 2, 3]);
+        assert_eq!(matrix.full_lines(2), [2, 3]);
+    }
+
     #[test]
     fn cell_iter() {
-        let mut matrix = Matrix::new();
-        matrix[Coordinate::new(2, 0)] = Some(Color::Yellow);
-        matrix[Coordinate::new(3, 1)] = Some(Color::Cyan);
+        let matrix: Matrix = "
+            ...I......
+            ..O.......
+        "
+        .parse()
+        .expect("Should be a valid board");
 
         let mut iter = CellIter {
             position: Coordinate::origin(),
@@ -1,20 +1,44 @@
+mod ascii;
 mod geometry;
+mod gravity;
+mod hold;
 mod matrix;
 mod piece;
+mod piece_set;
+mod spawn;
+mod visibility;
 
+pub mod fumen;
 pub mod kick_tables;
 
 use std::{option::Option, time::Duration};
 
 use cgmath::EuclideanSpace;
+use rand::{rngs::StdRng, SeedableRng};
 
-use self::piece::Piece;
+use self::{
+    fumen::{FumenError, Page},
+    matrix::clear_rows,
+    piece::Piece,
+};
 
 pub use self::{
-    matrix::{CellIter, Color, Matrix},
+    ascii::ParseBoardError,
+    gravity::{Gravity, GravityCurve, ParseGravityCurveError},
+    hold::{HoldError, HoldPolicy},
+    matrix::{Cell, CellIter, Color, Matrix},
     piece::{Kind as PieceKind, RotateKind, Rotation},
+    piece_set::{ParsePieceSetError, PieceSet},
+    spawn::SpawnRule,
+    visibility::StackVisibility,
 };
 
+/// Score for clearing 0 to 4 lines at once, times the level.
+const LINE_SCORES: [u32; 5] = [0, 100, 300, 500, 800];
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
 pub type Coordinate = cgmath::Point2<usize>;
 pub type Offset = cgmath::Vector2<isize>;
 
@@ -31,12 +55,33 @@ impl MoveKind {
     }
 }
 
+#[derive(Clone)]
 pub struct Engine {
     matrix: Matrix,
+    piece_set: &'static PieceSet,
     bag: Vec<PieceKind>,
+    /// Shuffles the bag, seeded so a game can be played again.
+    rng: StdRng,
     cursor: Option<Piece>,
-    held_cursor: Option<Piece>,
-    level: u8,
+    /// Held pieces, the front one is swapped in on hold.
+    held: Vec<Piece>,
+    hold_policy: HoldPolicy,
+    hold_used: bool,
+    block_size: usize,
+    spawn_rule: SpawnRule,
+    gravity_curve: GravityCurve,
+    level: u32,
+    /// Lines cleared so far, counted in big lines in big mode.
+    lines: u32,
+    score: u32,
+    history: Vec<Page>,
+
+    visibility: StackVisibility,
+    /// Time the engine has been running, advanced by the game.
+    clock: Duration,
+    /// When each matrix cell was last locked, by clock time.
+    lock_times: [Duration; Matrix::SIZE],
+    revealed_until: Duration,
 }
 
 impl Default for Engine {
@@ -46,33 +91,199 @@ impl Default for Engine {
 }
 
 impl Engine {
-    pub(crate) fn new() -> Self {
+    /// Pieces shown ahead in the queue.
+    pub const PREVIEW_COUNT: usize = 5;
+
+    pub fn new() -> Self {
         Engine {
             matrix: Matrix::new(),
+            piece_set: PieceSet::TETROMINOES,
             bag: Vec::new(),
+            rng: StdRng::from_entropy(),
             cursor: None,
-            held_cursor: None,
+            held: Vec::new(),
+            hold_policy: HoldPolicy::Standard,
+            hold_used: false,
+            block_size: 1,
+            spawn_rule: SpawnRule::Guideline,
+            gravity_curve: GravityCurve::Guideline,
             level: 1,
+            lines: 0,
+            score: 0,
+            history: Vec::new(),
+            visibility: StackVisibility::Visible,
+            clock: Duration::ZERO,
+            lock_times: [Duration::ZERO; Matrix::SIZE],
+            revealed_until: Duration::ZERO,
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn from_matrix(matrix: Matrix) -> Self {
+    pub fn from_matrix(matrix: Matrix) -> Self {
         Engine {
             matrix,
             ..Self::new()
         }
     }
 
+    pub fn with_piece_set(self, piece_set: &'static PieceSet) -> Self {
+        Engine {
+            piece_set,
+            bag: Vec::new(),
+            ..self
+        }
+    }
+
+    /// Big mode: every mino covers a square of `block_size` cells, so the
+    /// matrix plays like one with `block_size` times fewer rows and columns.
+    pub fn with_block_size(self, block_size: usize) -> Self {
+        assert!(
+            Matrix::WIDTH.is_multiple_of(block_size)
+                && Matrix::HEIGHT.is_multiple_of(block_size),
+            "Block size must divide the matrix dimensions"
+        );
+        Engine { block_size, ..self }
+    }
+
+    /// Seeds the randomizer: the same seed deals the same pieces.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Engine {
+            rng: StdRng::seed_from_u64(seed),
+            bag: Vec::new(),
+            ..self
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn with_level(self, level: u32) -> Self {
+        Engine { level, ..self }
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.lines
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn with_gravity_curve(self, gravity_curve: GravityCurve) -> Self {
+        Engine {
+            gravity_curve,
+            ..self
+        }
+    }
+
+    pub fn with_spawn_rule(self, spawn_rule: SpawnRule) -> Self {
+        Engine { spawn_rule, ..self }
+    }
+
+    pub fn with_hold_policy(self, hold_policy: HoldPolicy) -> Self {
+        Engine {
+            hold_policy,
+            ..self
+        }
+    }
+
+    pub fn with_stack_visibility(self, visibility: StackVisibility) -> Self {
+        Engine { visibility, ..self }
+    }
+
+    /// Advances the clock that lock times are measured with.
+    pub(crate) fn advance_clock(&mut self, delta: Duration) {
+        self.clock += delta;
+    }
+
+    /// Shows the whole stack for `duration`, whatever its visibility.
+    pub(crate) fn reveal_stack(&mut self, duration: Duration) {
+        let until = self.clock.saturating_add(duration);
+        self.revealed_until = self.revealed_until.max(until);
+    }
+
+    /// Loads the first page of a fumen as a practice setup.
+    pub fn from_fumen(fumen: &str) -> Result<Self, FumenError> {
+        let page = fumen::decode(fumen)?
+            .into_iter()
+            .next()
+            .ok_or(FumenError::UnexpectedEnd)?;
+
+        Ok(Engine {
+            matrix: page.matrix,
+            cursor: page.piece,
+            held: page.hold.map(Piece::new).into_iter().collect(),
+            ..Self::new()
+        })
+    }
+
+    /// Exports the current board, cursor and hold as a single page fumen.
+    pub fn to_fumen(&self) -> String {
+        fumen::encode(&[self.fumen_page()])
+    }
+
+    /// Exports every placement so far, followed by the current board.
+    pub fn history_to_fumen(&self) -> String {
+        let mut pages = self.history.clone();
+        pages.push(self.fumen_page());
+        fumen::encode(&pages)
+    }
+
     pub(crate) fn add_cursor(&mut self) {
-        if self.bag.is_empty() {
+        //NOTE: kept full enough to show the next pieces
+        while self.bag.len() <= Self::PREVIEW_COUNT {
             self.refill_bag();
         }
         let kind = self.bag.pop().expect("Bag is empty");
-        self.cursor = Some(Piece::new(kind));
+        self.spawn(kind);
+    }
+
+    /// Makes a new cursor of the given kind where the spawn rule puts it.
+    fn spawn(&mut self, kind: PieceKind) {
+        let cursor = Piece::sized(kind, self.block_size as isize)
+            .moved_by(self.spawn_rule.offset());
+        self.cursor = Some(cursor);
+
+        if self.spawn_rule.drops_on_spawn() {
+            if let Some(dropped) = self.ticked_down_cursor() {
+                self.cursor = Some(dropped);
+            }
+        }
     }
 
-    pub(crate) fn cursor_has_hit_bottom(&self) -> bool {
+    /// Whether the cursor overlaps the stack, which happens when a piece
+    /// spawns on top of it.
+    pub fn cursor_is_blocked(&self) -> bool {
+        self.cursor
+            .is_some_and(|cursor| self.matrix.is_clipping(&cursor))
+    }
+
+    /// Whether the cursor reaches above the matrix, where it cannot lock.
+    pub fn cursor_is_out(&self) -> bool {
+        self.cursor.is_some_and(|cursor| {
+            cursor.cells().is_none_or(|cells| {
+                cells.iter().any(|coord| !Matrix::on_matrix(*coord))
+            })
+        })
+    }
+
+    /// Where the cursor would land if hard dropped.
+    pub fn ghost_cells(&self) -> Option<Vec<Coordinate>> {
+        let mut ghost = self.cursor?;
+        loop {
+            let dropped = ghost.moved_by(Offset::new(0, -1));
+            if self.matrix.is_clipping(&dropped) {
+                return ghost.cells();
+            }
+            ghost = dropped;
+        }
+    }
+
+    pub fn cursor_has_hit_bottom(&self) -> bool {
         self.ticked_down_cursor().is_none()
     }
 
@@ -95,10 +306,10 @@ impl Engine {
         }
     }
 
+    /// Moves the cursor a cell, failing when it is blocked or there is no
+    /// cursor to move.
     pub(crate) fn move_cursor(&mut self, kind: MoveKind) -> Result<(), ()> {
-        let Some(cursor) = self.cursor.as_mut() else {
-            return Ok(());
-        };
+        let cursor = self.cursor.as_mut().ok_or(())?;
 
         let new_cursor = cursor.moved_by(kind.offset());
         if new_cursor.cells().is_none() {
@@ -113,6 +324,12 @@ impl Engine {
         Ok(())
     }
 
+    /// Row of the cursor position, only changed by moving it up or down.
+    pub(crate) fn cursor_row(&self) -> isize {
+        let cursor = self.cursor.expect("Cursor row asked without cursor");
+        cursor.position.y
+    }
+
     pub(crate) fn tick_down(&mut self) {
         self.cursor = Some(
             self.ticked_down_cursor()
@@ -129,49 +346,112 @@ impl Engine {
         // self.place_cursor()
     }
 
-    pub(crate) fn drop_time(&self) -> Duration {
-        let level = self.level - 1;
-
-        Duration::from_secs_f32(
-            (0.8 - ((level) as f32 * 0.007)).powi(level as _),
-        )
+    pub fn gravity(&self) -> Gravity {
+        self.gravity_curve.gravity(self.level)
     }
 
-    pub(crate) fn cells(&self) -> CellIter<'_> {
+    pub fn cells(&self) -> CellIter<'_> {
         CellIter {
             position: Coordinate::origin(),
             cells: self.matrix.0.iter(),
         }
     }
 
-    pub(crate) fn line_clear(&mut self, mut animation: impl FnMut(&[usize])) {
-        let lines: Vec<usize> = self.matrix.full_lines();
+    /// Locked cells as they should be drawn, with their opacity. Cells
+    /// hidden by the stack visibility are reported as empty.
+    pub fn visible_cells(
+        &self,
+    ) -> impl Iterator<Item = (Coordinate, Option<(Color, f32)>)> + '_ {
+        let revealed = self.clock < self.revealed_until;
+        self.cells().zip(self.lock_times.iter()).map(
+            move |((coord, cell), &locked_at)| {
+                let opacity = match revealed {
+                    true => 1.0,
+                    false => self
+                        .visibility
+                        .opacity(self.clock.saturating_sub(locked_at)),
+                };
+                let cell = cell.filter(|_| opacity > 0.0);
+                (coord, cell.map(|color| (color, opacity)))
+            },
+        )
+    }
+
+    pub fn full_lines(&self) -> Vec<usize> {
+        self.matrix.full_lines(self.block_size)
+    }
+
+    /// Removes the given lines, usually the [`Engine::full_lines`], and
+    /// scores them.
+    pub(crate) fn clear_lines(&mut self, lines: &[usize]) {
         if lines.is_empty() {
             return;
         }
-        animation(lines.as_slice());
-        self.matrix.clear_lines(lines.as_slice());
+        let cleared = lines.len() / self.block_size;
+        self.lines += cleared as u32;
+        self.score = self.score.saturating_add(
+            LINE_SCORES[cleared.min(4)].saturating_mul(self.level),
+        );
+
+        self.matrix.clear_lines(lines);
+        clear_rows(&mut self.lock_times, lines, Duration::ZERO);
+        self.reveal_stack(StackVisibility::REVEAL_TIME);
+    }
+
+    /// A hash of the locked cells, to check two games ended on the same
+    /// board. It is FNV-1a, so it stays the same across builds.
+    pub fn checksum(&self) -> u64 {
+        self.matrix
+            .to_string()
+            .bytes()
+            .fold(FNV_OFFSET, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
     }
 
+    /// Adds a shuffled bag, drawn from once the pieces left are.
     pub(crate) fn refill_bag(&mut self) {
-        debug_assert!(self.bag.is_empty());
         use rand::seq::SliceRandom;
-        use rand::thread_rng;
 
-        let mut rng = thread_rng();
-        self.bag.extend_from_slice(PieceKind::ALL.as_slice());
-        self.bag.shuffle(&mut rng);
+        let mut bag = self.piece_set.kinds().collect::<Vec<_>>();
+        bag.shuffle(&mut self.rng);
+        //NOTE: pieces are drawn from the back
+        bag.append(&mut self.bag);
+        self.bag = bag;
+    }
+
+    /// The pieces coming after the cursor, next one first.
+    pub fn next_pieces(&self) -> Vec<(Vec<Offset>, Color)> {
+        self.bag
+            .iter()
+            .rev()
+            .take(Self::PREVIEW_COUNT)
+            .map(|kind| (Piece::new(*kind).default_cells(), kind.color()))
+            .collect()
     }
 
-    pub(crate) fn hold_cursor(&mut self) {
-        let cursor = self.cursor.take().expect("No cursor").reset();
-        if self.held_cursor.is_none() {
-            self.held_cursor = Some(cursor);
+    pub(crate) fn hold_cursor(&mut self) -> Result<(), HoldError> {
+        self.hold_policy.check(self.hold_used)?;
+        let cursor = self.cursor.take().ok_or(HoldError::NoCursor)?.reset();
+        self.hold_used = true;
+
+        if self.held.len() < self.hold_policy.slots() {
+            self.held.push(cursor);
             self.add_cursor();
         } else {
-            self.cursor = self.held_cursor;
-            self.held_cursor = Some(cursor);
+            let held = std::mem::replace(&mut self.held[0], cursor);
+            self.spawn(held.kind);
+        }
+        Ok(())
+    }
+
+    /// Moves the front held piece to the back of the hold queue.
+    pub(crate) fn cycle_hold(&mut self) -> Result<(), HoldError> {
+        if self.held.is_empty() {
+            return Err(HoldError::NothingHeld);
         }
+        self.held.rotate_left(1);
+        Ok(())
     }
 
     pub(crate) fn place_cursor(&mut self) {
@@ -186,13 +466,22 @@ impl Engine {
             cursor
         );
 
+        let page = Page {
+            piece: Some(cursor),
+            lock: true,
+            ..self.fumen_page()
+        };
+        self.history.push(page);
+        self.hold_used = false;
+
         let color = cursor.kind.color();
         for coords in cursor.cells().expect("cursor out of bounds !??!?!") {
             self.matrix[coords] = Some(color);
+            self.lock_times[Matrix::indexing(coords)] = self.clock;
         }
     }
 
-    pub(crate) fn cursor_info(
+    pub fn cursor_info(
         &self,
     ) -> Option<(Vec<Coordinate>, Color, PieceKind, Rotation)> {
         let cursor = self.cursor?;
@@ -204,9 +493,22 @@ impl Engine {
         ))
     }
 
-    pub(crate) fn held_cursor_info(&self) -> Option<(Vec<Offset>, Color)> {
-        let cursor = self.held_cursor?;
-        Some((cursor.default_cells(), cursor.kind.color()))
+    /// The held pieces, front slot first.
+    pub fn held_cursor_info(&self) -> Vec<(Vec<Offset>, Color)> {
+        self.held
+            .iter()
+            .map(|held| (held.default_cells(), held.kind.color()))
+            .collect()
+    }
+
+    fn fumen_page(&self) -> Page {
+        Page {
+            matrix: self.matrix.clone(),
+            piece: self.cursor,
+            hold: self.held.first().map(|held| held.kind),
+            comment: String::new(),
+            lock: false,
+        }
     }
 
     fn ticked_down_cursor(&self) -> Option<Piece> {
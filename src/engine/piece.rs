@@ -1,4 +1,8 @@
-use super::{matrix::Color, Coordinate, Matrix, Offset};
+use super::{
+    matrix::Color,
+    piece_set::{KickTable, PieceDef, PieceSet, TETROMINO_DEFS},
+    Coordinate, Matrix, Offset,
+};
 use cgmath::{EuclideanSpace, Zero};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -30,12 +34,16 @@ impl std::ops::Add<RotateKind> for Rotation {
 pub enum Rotation { N, E, S, W }
 
 impl Rotation {
-    fn i_offset(&self) -> Offset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::iter().find(|rotation| rotation.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
         match self {
-            Rotation::N => Offset::zero(),
-            Rotation::E => Offset::new(1, 0),
-            Rotation::S => Offset::new(1, -1),
-            Rotation::W => Offset::new(0, -1),
+            Self::N => "N",
+            Self::E => "E",
+            Self::S => "S",
+            Self::W => "W",
         }
     }
 }
@@ -53,41 +61,61 @@ impl std::ops::Mul<Rotation> for Offset {
     }
 }
 
-#[rustfmt::skip]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Kind { I, O, T, S, Z, J, L }
+/// A piece kind, referring to its definition in a [`PieceSet`].
+/// Kinds are equal only if they share the same definition, so an I
+/// tromino is not an I tetromino.
+#[derive(Clone, Copy)]
+pub struct Kind(pub(super) &'static PieceDef);
 
 impl Kind {
-    const KIND_COUNT: usize = 7;
+    pub const I: Self = Self(&TETROMINO_DEFS[0]);
+    pub const O: Self = Self(&TETROMINO_DEFS[1]);
+    pub const T: Self = Self(&TETROMINO_DEFS[2]);
+    pub const S: Self = Self(&TETROMINO_DEFS[3]);
+    pub const Z: Self = Self(&TETROMINO_DEFS[4]);
+    pub const J: Self = Self(&TETROMINO_DEFS[5]);
+    pub const L: Self = Self(&TETROMINO_DEFS[6]);
 
-    #[rustfmt::skip]
-    pub(super) const ALL: [Kind; Self::KIND_COUNT] = [
-        Self::I, Self::O, Self::T, Self::S, Self::Z, Self::J, Self::L,
-    ];
+    pub(super) fn cells(&self) -> &'static [Offset] {
+        let def: &'static PieceDef = self.0;
+        &def.cells
+    }
 
-    #[rustfmt::skip]
-    fn cells(&self) -> [Offset; Piece::CELL_COUNT]{
-        match self {
-            Kind::O => &[( 0, 0), (1, 0), (0, 1), ( 1, 1)],
-            Kind::I => &[(-1, 0), (0, 0), (1, 0), ( 2, 0)],
-            Kind::T => &[(-1, 0), (0, 0), (1, 0), ( 0, 1)],
-            Kind::L => &[(-1, 0), (0, 0), (1, 0), ( 1, 1)],
-            Kind::J => &[(-1, 0), (0, 0), (1, 0), (-1, 1)],
-            Kind::S => &[(-1, 0), (0, 0), (0, 1), ( 1, 1)],
-            Kind::Z => &[(-1, 1), (0, 1), (0, 0), ( 1, 0)],
-        }.map(Offset::from)
+    pub(super) fn kick_table(&self) -> &'static KickTable {
+        &self.0.kicks
+    }
+
+    pub fn letter(&self) -> char {
+        self.0.letter
+    }
+
+    /// Looks up a tetromino by letter.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        PieceSet::TETROMINOES.kind(letter)
     }
 
     pub fn color(&self) -> Color {
-        match self {
-            Kind::I => Color::Cyan,
-            Kind::O => Color::Yellow,
-            Kind::T => Color::Purple,
-            Kind::S => Color::Green,
-            Kind::Z => Color::Red,
-            Kind::J => Color::Blue,
-            Kind::L => Color::Orange,
-        }
+        self.0.color
+    }
+}
+
+impl PartialEq for Kind {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Kind {}
+
+impl std::hash::Hash for Kind {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state)
+    }
+}
+
+impl std::fmt::Debug for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Kind({})", self.letter())
     }
 }
 
@@ -96,30 +124,74 @@ pub(super) struct Piece {
     pub kind: Kind,
     pub position: Offset,
     pub rotation: Rotation,
+    /// Side length of the square of matrix cells each mino covers.
+    /// Positions stay in matrix cells, moves are made in whole blocks.
+    pub block_size: isize,
 }
 
 impl Piece {
-    const CELL_COUNT: usize = 4;
-    const SPAWN_POSITION: Offset =
-        Offset::new((Matrix::WIDTH / 2) as isize, Matrix::HEIGHT as isize);
-
     pub fn new(kind: Kind) -> Self {
-        //TODO: look into integrating this with the Kind cells array
-        let spawn_offset = match kind {
-            Kind::I | Kind::O => Offset::new(-1, 0),
-            _ => Offset::zero(),
-        };
+        Self::sized(kind, 1)
+    }
+
+    /// Spawns a piece in the middle of the matrix. Its column is worked
+    /// out in cells, then rounded to the nearest whole block, so big pieces
+    /// stay centered on a field an odd number of blocks wide.
+    pub fn sized(kind: Kind, block_size: isize) -> Self {
+        let spawn_offset = kind.0.spawn_offset;
+        let column = Matrix::WIDTH as isize / 2 + spawn_offset.x * block_size;
+        let row = Matrix::HEIGHT as isize / block_size + spawn_offset.y;
 
         Piece {
             kind,
-            position: Self::SPAWN_POSITION + spawn_offset,
+            position: Offset::new(
+                (column + block_size / 2).div_euclid(block_size),
+                row,
+            ) * block_size,
             rotation: Rotation::N,
+            block_size,
         }
     }
 
+    /// Finds the piece of the given kind that occupies exactly `cells`, in
+    /// `rotation` if given. Otherwise rotations are tried in order, so
+    /// shapes that look the same in several rotations resolve to the first
+    /// one.
+    pub(super) fn covering(
+        kind: Kind,
+        cells: &[Coordinate],
+        rotation: Option<Rotation>,
+    ) -> Option<Self> {
+        let by_row = |coord: &Coordinate| (coord.y, coord.x);
+        let mut cells = cells.to_vec();
+        cells.sort_by_key(by_row);
+        let anchor = cells.first()?.to_vec().cast::<isize>()?;
+
+        let mut rotations = Rotation::iter()
+            .filter(|candidate| rotation.is_none_or(|r| r == *candidate));
+        rotations.find_map(|rotation| {
+            let piece = Piece {
+                kind,
+                position: Offset::zero(),
+                rotation,
+                block_size: 1,
+            };
+            let offset_anchor = piece
+                .offsets()
+                .into_iter()
+                .min_by_key(|offset| (offset.y, offset.x))?;
+            let piece = piece.moved_by(anchor - offset_anchor);
+
+            let mut piece_cells = piece.cells()?;
+            piece_cells.sort_by_key(by_row);
+            (piece_cells == cells).then_some(piece)
+        })
+    }
+
+    /// Moves the piece by `offset` blocks.
     pub(super) fn moved_by(&self, offset: Offset) -> Self {
         Self {
-            position: self.position + offset,
+            position: self.position + offset * self.block_size,
             ..*self
         }
     }
@@ -130,30 +202,46 @@ impl Piece {
     }
 
     pub(super) fn reset(self) -> Self {
-        Self::new(self.kind)
+        Self::sized(self.kind, self.block_size)
     }
 
     /// Returns the cells of this [`Piece`].
     /// If the piece is out of bounds, `None` is returned.
     pub(super) fn cells(&self) -> Option<Vec<Coordinate>> {
+        let block = (0..self.block_size)
+            .flat_map(|y| (0..self.block_size).map(move |x| Offset::new(x, y)));
+
+        self.offsets()
+            .into_iter()
+            .flat_map(|offset| {
+                block
+                    .clone()
+                    .map(move |cell| offset * self.block_size + cell)
+            })
+            .map(self.positioner())
+            .collect::<Option<Vec<_>>>()
+    }
+
+    /// Returns the cells of this [`Piece`] relative to its position,
+    /// in blocks.
+    pub(super) fn offsets(&self) -> Vec<Offset> {
         self.kind
             .cells()
+            .iter()
+            .copied()
             .map(self.rotator())
-            .map(self.positioner())
-            .into_iter()
-            .collect::<Option<Vec<_>>>()
+            .collect()
     }
 
     pub(super) fn default_cells(&self) -> Vec<Offset> {
-        self.kind.cells().into_iter().collect()
+        self.kind.cells().to_vec()
     }
 
+    /// Rotates cells around the piece's rotation center. The center is
+    /// given in half cells, so the math is done at double scale.
     fn rotator(&self) -> impl Fn(Offset) -> Offset + '_ {
-        move |offset| match self.kind {
-            Kind::O => offset,
-            Kind::I => offset * self.rotation + self.rotation.i_offset(),
-            _ => offset * self.rotation,
-        }
+        let center = self.kind.0.rotation_center;
+        move |offset| ((offset * 2 - center) * self.rotation + center) / 2
     }
 
     fn positioner(&self) -> impl Fn(Offset) -> Option<Coordinate> + '_ {
@@ -178,6 +266,7 @@ mod test {
             kind: Kind::O,
             position: Offset::zero(),
             rotation: Rotation::N,
+            block_size: 1,
         };
 
         let cells = piece.cells().expect("Should be a valid O piece");
@@ -199,17 +288,37 @@ mod test {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::N,
+            block_size: 1,
         };
         let _ = piece.cells().unwrap();
     }
 
+    #[test]
+    fn test_cells_big() {
+        let piece = Piece::sized(Kind::T, 2);
+        assert_eq!(piece.position, Offset::new(4, 20));
+
+        let piece = piece.moved_by(Offset::new(1, -1));
+        assert_eq!(piece.position, Offset::new(6, 18));
+
+        let mut cells = piece.cells().expect("Should be a valid T piece");
+        cells.sort_by_key(|coord| (coord.y, coord.x));
+        assert_eq!(cells.len(), 16);
+        assert_eq!(
+            cells[..6],
+            (4..10).map(|x| Coordinate::new(x, 18)).collect::<Vec<_>>()
+        );
+        assert_eq!(cells[15], Coordinate::new(7, 21));
+    }
+
     #[rstest]
     //NOTE: case 1
     #[case(
         Piece{
             kind: Kind::Z,
             position: Offset::new(5, 6),
-            rotation: Rotation::W
+            rotation: Rotation::W,
+            block_size: 1,
         },
         vec![
             Coordinate::new(4, 5),
@@ -223,7 +332,8 @@ mod test {
         Piece{
             kind: Kind::L,
             position: Offset::new(8, 2),
-            rotation: Rotation::S
+            rotation: Rotation::S,
+            block_size: 1,
         },
         vec![
             Coordinate::new(9, 2),
@@ -237,7 +347,8 @@ mod test {
         Piece{
             kind: Kind::I,
             position: Offset::new(5,5),
-            rotation: Rotation::N
+            rotation: Rotation::N,
+            block_size: 1,
         },
         vec![
             Coordinate::new(4,5),
@@ -251,7 +362,8 @@ mod test {
         Piece{
             kind: Kind::I,
             position: Offset::new(5,5),
-            rotation: Rotation::E
+            rotation: Rotation::E,
+            block_size: 1,
         },
         vec![
             Coordinate::new(6,6),
@@ -265,7 +377,8 @@ mod test {
         Piece{
             kind: Kind::I,
             position: Offset::new(5,5),
-            rotation: Rotation::S
+            rotation: Rotation::S,
+            block_size: 1,
         },
         vec![
             Coordinate::new(7,4),
@@ -279,7 +392,8 @@ mod test {
         Piece{
             kind: Kind::I,
             position: Offset::new(5,5),
-            rotation: Rotation::W
+            rotation: Rotation::W,
+            block_size: 1,
         },
         vec![
             Coordinate::new(5,3),
@@ -302,12 +416,14 @@ mod test {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::N,
+            block_size: 1,
         },
         super::RotateKind::Clockwise,
         Piece {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::E,
+            block_size: 1,
         }
     )]
     #[case (
@@ -315,12 +431,14 @@ mod test {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::N,
+            block_size: 1,
         },
         super::RotateKind::CounterClockwise,
         Piece {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::W,
+            block_size: 1,
         }
     )]
     #[case (
@@ -328,12 +446,14 @@ mod test {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::S,
+            block_size: 1,
         },
         super::RotateKind::CounterClockwise,
         Piece {
             kind: Kind::I,
             position: Offset::zero(),
             rotation: Rotation::E,
+            block_size: 1,
         }
     )]
     fn test_rotated_by(
@@ -0,0 +1,509 @@
+//! Data definitions of the pieces a game is played with.
+//!
+//! The classic tetrominoes are the default [`PieceSet`]. Other sets can be
+//! loaded from a small text format, one block per piece:
+//!
+//! ```text
+//! # comments start with '#'
+//! piece I
+//! color cyan
+//! kicks srs-i
+//! center 0.5,-0.5
+//! spawn -1,0
+//! cells -1,0 0,0 1,0 2,0
+//! ```
+//!
+//! `cells` is required, `color` defaults to gray, `kicks` to `none` and
+//! `center` / `spawn` to `0,0`. Besides `none`, `srs` and `srs-i`, kicks
+//! can be given as a custom table with one `kick <N|E|S|W> x,y ...` line
+//! per rotation, listing the clockwise kicks from that rotation.
+//!
+//! `spawn` moves the piece from the middle column of the matrix. Pieces
+//! should spawn centered, rounding to the left like the guideline does.
+
+use std::{borrow::Cow, collections::HashMap, fmt, str::FromStr, sync::Mutex};
+
+use cgmath::Zero;
+use once_cell::sync::Lazy;
+
+use super::{matrix::Color, piece::Kind, Offset};
+
+pub struct PieceSet {
+    pieces: Cow<'static, [PieceDef]>,
+}
+
+#[derive(Clone)]
+pub struct PieceDef {
+    pub(super) letter: char,
+    pub(super) cells: Cow<'static, [Offset]>,
+    /// Rotation center in half cells, so that pieces like I and O can
+    /// rotate around the corner between cells.
+    pub(super) rotation_center: Offset,
+    pub(super) spawn_offset: Offset,
+    pub(super) color: Color,
+    pub(super) kicks: KickTable,
+}
+
+#[derive(Clone)]
+pub enum KickTable {
+    None,
+    SrsPlus,
+    SrsPlusI,
+    /// Clockwise kicks from N, E, S and W. Counter-clockwise kicks are the
+    /// clockwise kicks of the opposite transition, with the opposite sign.
+    Custom([Cow<'static, [Offset]>; 4]),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsePieceSetError {
+    Empty,
+    MissingPiece { line: usize },
+    UnknownKey { line: usize },
+    InvalidValue { line: usize },
+    DuplicateLetter(char),
+    MissingCells(char),
+}
+
+impl fmt::Display for ParsePieceSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no pieces defined"),
+            Self::MissingPiece { line } => {
+                write!(f, "setting before any piece on line {}", line + 1)
+            }
+            Self::UnknownKey { line } => {
+                write!(f, "unknown key on line {}", line + 1)
+            }
+            Self::InvalidValue { line } => {
+                write!(f, "invalid value on line {}", line + 1)
+            }
+            Self::DuplicateLetter(letter) => {
+                write!(f, "piece {letter} is defined twice")
+            }
+            Self::MissingCells(letter) => {
+                write!(f, "piece {letter} has no cells")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParsePieceSetError {}
+
+const fn cell(x: isize, y: isize) -> Offset {
+    Offset::new(x, y)
+}
+
+const fn cells(cells: &'static [Offset]) -> Cow<'static, [Offset]> {
+    Cow::Borrowed(cells)
+}
+
+#[rustfmt::skip]
+pub(super) static TETROMINO_DEFS: [PieceDef; 7] = [
+    PieceDef {
+        letter: 'I',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(1, 0), cell(2, 0)]),
+        rotation_center: cell(1, -1),
+        spawn_offset: cell(-1, 0),
+        color: Color::Cyan,
+        kicks: KickTable::SrsPlusI,
+    },
+    PieceDef {
+        letter: 'O',
+        cells: cells(&[cell(0, 0), cell(1, 0), cell(0, 1), cell(1, 1)]),
+        rotation_center: cell(1, 1),
+        spawn_offset: cell(-1, 0),
+        color: Color::Yellow,
+        kicks: KickTable::None,
+    },
+    PieceDef {
+        letter: 'T',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(1, 0), cell(0, 1)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Purple,
+        kicks: KickTable::SrsPlus,
+    },
+    PieceDef {
+        letter: 'S',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(0, 1), cell(1, 1)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Green,
+        kicks: KickTable::SrsPlus,
+    },
+    PieceDef {
+        letter: 'Z',
+        cells: cells(&[cell(-1, 1), cell(0, 1), cell(0, 0), cell(1, 0)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Red,
+        kicks: KickTable::SrsPlus,
+    },
+    PieceDef {
+        letter: 'J',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(1, 0), cell(-1, 1)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Blue,
+        kicks: KickTable::SrsPlus,
+    },
+    PieceDef {
+        letter: 'L',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(1, 0), cell(1, 1)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Orange,
+        kicks: KickTable::SrsPlus,
+    },
+];
+
+#[rustfmt::skip]
+static TROMINO_DEFS: [PieceDef; 2] = [
+    PieceDef {
+        letter: 'I',
+        cells: cells(&[cell(-1, 0), cell(0, 0), cell(1, 0)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Cyan,
+        kicks: KickTable::SrsPlus,
+    },
+    PieceDef {
+        letter: 'L',
+        cells: cells(&[cell(0, 0), cell(1, 0), cell(0, 1)]),
+        rotation_center: cell(0, 0),
+        spawn_offset: cell(-1, 0),
+        color: Color::Orange,
+        kicks: KickTable::SrsPlus,
+    },
+];
+
+/// Sets parsed by [`PieceSet::interned`], by their definition.
+static PARSED_SETS: Lazy<Mutex<HashMap<String, &'static PieceSet>>> =
+    Lazy::new(Default::default);
+
+impl PieceSet {
+    pub const TETROMINOES: &'static Self = &Self {
+        pieces: Cow::Borrowed(&TETROMINO_DEFS),
+    };
+
+    pub const TROMINOES: &'static Self = &Self {
+        pieces: Cow::Borrowed(&TROMINO_DEFS),
+    };
+
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        match name {
+            "tetromino" => Some(Self::TETROMINOES),
+            "tromino" => Some(Self::TROMINOES),
+            _ => None,
+        }
+    }
+
+    /// Parses a piece set once per definition, the same text always gives
+    /// the same set back. Parsed sets are leaked, since piece kinds refer to
+    /// them for the rest of the program.
+    pub fn interned(text: &str) -> Result<&'static Self, ParsePieceSetError> {
+        let mut sets = PARSED_SETS.lock().unwrap();
+        if let Some(set) = sets.get(text) {
+            return Ok(set);
+        }
+        let set: &'static Self = Box::leak(Box::new(text.parse()?));
+        sets.insert(text.to_owned(), set);
+        Ok(set)
+    }
+
+    pub fn kinds(&'static self) -> impl Iterator<Item = Kind> {
+        self.pieces.iter().map(Kind)
+    }
+
+    pub fn kind(&'static self, letter: char) -> Option<Kind> {
+        self.kinds().find(|kind| kind.letter() == letter)
+    }
+}
+
+/// Parses a piece set. Its kinds are only usable once it lives for the
+/// rest of the program, see [`PieceSet::interned`].
+impl FromStr for PieceSet {
+    type Err = ParsePieceSetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut builders: Vec<PieceBuilder> = Vec::new();
+
+        for (index, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(char::is_whitespace)
+            else {
+                if line.is_empty() {
+                    continue;
+                }
+                return Err(ParsePieceSetError::InvalidValue { line: index });
+            };
+
+            let invalid = ParsePieceSetError::InvalidValue { line: index };
+            if key == "piece" {
+                let mut letters = value.trim().chars();
+                let (Some(letter), None) = (letters.next(), letters.next())
+                else {
+                    return Err(invalid);
+                };
+                if builders.iter().any(|builder| builder.letter == letter) {
+                    return Err(ParsePieceSetError::DuplicateLetter(letter));
+                }
+                builders.push(PieceBuilder::new(letter));
+                continue;
+            }
+
+            let builder = builders
+                .last_mut()
+                .ok_or(ParsePieceSetError::MissingPiece { line: index })?;
+            builder
+                .set(key, value.trim())
+                .ok_or(invalid)?
+                .ok_or(ParsePieceSetError::UnknownKey { line: index })?;
+        }
+
+        if builders.is_empty() {
+            return Err(ParsePieceSetError::Empty);
+        }
+
+        let pieces = builders
+            .into_iter()
+            .map(PieceBuilder::build)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            pieces: pieces.into(),
+        })
+    }
+}
+
+struct PieceBuilder {
+    letter: char,
+    cells: Vec<Offset>,
+    rotation_center: Offset,
+    spawn_offset: Offset,
+    color: Color,
+    kicks: Option<KickTable>,
+    custom_kicks: [Vec<Offset>; 4],
+}
+
+impl PieceBuilder {
+    fn new(letter: char) -> Self {
+        Self {
+            letter,
+            cells: Vec::new(),
+            rotation_center: Offset::zero(),
+            spawn_offset: Offset::zero(),
+            color: Color::Gray,
+            kicks: None,
+            custom_kicks: Default::default(),
+        }
+    }
+
+    /// Returns `None` on an invalid value and `Some(None)` on an unknown key.
+    fn set(&mut self, key: &str, value: &str) -> Option<Option<()>> {
+        match key {
+            "cells" => self.cells = parse_offsets(value)?,
+            "spawn" => self.spawn_offset = parse_offset(value)?,
+            "center" => self.rotation_center = parse_center(value)?,
+            "color" => self.color = parse_color(value)?,
+            "kicks" => {
+                self.kicks = Some(match value {
+                    "none" => KickTable::None,
+                    "srs" => KickTable::SrsPlus,
+                    "srs-i" => KickTable::SrsPlusI,
+                    _ => return None,
+                })
+            }
+            "kick" => {
+                let (rotation, kicks) =
+                    value.split_once(char::is_whitespace)?;
+                let index = ["N", "E", "S", "W"]
+                    .iter()
+                    .position(|&name| name == rotation)?;
+                self.custom_kicks[index] = parse_offsets(kicks)?;
+            }
+            _ => return Some(None),
+        }
+        Some(Some(()))
+    }
+
+    fn build(self) -> Result<PieceDef, ParsePieceSetError> {
+        if self.cells.is_empty() {
+            return Err(ParsePieceSetError::MissingCells(self.letter));
+        }
+
+        let kicks = match self.kicks {
+            Some(kicks) => kicks,
+            None if self.custom_kicks.iter().any(|kicks| !kicks.is_empty()) => {
+                KickTable::Custom(self.custom_kicks.map(Cow::Owned))
+            }
+            None => KickTable::None,
+        };
+
+        Ok(PieceDef {
+            letter: self.letter,
+            cells: self.cells.into(),
+            rotation_center: self.rotation_center,
+            spawn_offset: self.spawn_offset,
+            color: self.color,
+            kicks,
+        })
+    }
+}
+
+fn parse_offset(value: &str) -> Option<Offset> {
+    let (x, y) = value.split_once(',')?;
+    Some(Offset::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn parse_offsets(value: &str) -> Option<Vec<Offset>> {
+    value.split_whitespace().map(parse_offset).collect()
+}
+
+/// Parses a center like `0.5,-0.5` into half cells. Both coordinates need
+/// to be whole or both halves, otherwise cells would not stay on the grid.
+fn parse_center(value: &str) -> Option<Offset> {
+    let (x, y) = value.split_once(',')?;
+    let half_cells = |coord: &str| {
+        let doubled = coord.trim().parse::<f32>().ok()? * 2.0;
+        (doubled.fract() == 0.0).then_some(doubled as isize)
+    };
+    let center = Offset::new(half_cells(x)?, half_cells(y)?);
+    (center.x.rem_euclid(2) == center.y.rem_euclid(2)).then_some(center)
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    Some(match value {
+        "yellow" => Color::Yellow,
+        "cyan" => Color::Cyan,
+        "purple" => Color::Purple,
+        "orange" => Color::Orange,
+        "blue" => Color::Blue,
+        "green" => Color::Green,
+        "red" => Color::Red,
+        "gray" => Color::Gray,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::engine::piece::{Piece, Rotation};
+
+    const PENTOMINOES: &str = "
+        # a few pentominoes
+        piece P
+        color purple
+        kicks srs
+        cells -1,0 0,0 -1,1 0,1 0,2
+
+        piece I
+        color cyan
+        spawn -1,0
+        cells -2,0 -1,0 0,0 1,0 2,0
+        kick N -1,0 1,0
+        kick E 1,0 -1,0
+        kick S -1,0 1,0
+        kick W 1,0 -1,0
+    ";
+
+    #[test]
+    fn parse_pentominoes() {
+        let set = PieceSet::interned(PENTOMINOES)
+            .expect("Should be a valid piece set");
+
+        let kinds = set.kinds().collect::<Vec<_>>();
+        assert_eq!(kinds.len(), 2);
+        assert_eq!(kinds[0].letter(), 'P');
+        assert_eq!(kinds[0].color(), Color::Purple);
+        assert_eq!(kinds[1].cells().len(), 5);
+        assert!(matches!(kinds[1].kick_table(), KickTable::Custom(_)));
+
+        let piece = Piece {
+            kind: kinds[1],
+            position: Offset::new(5, 5),
+            rotation: Rotation::E,
+            block_size: 1,
+        };
+        let mut rows = piece
+            .cells()
+            .expect("Should fit")
+            .iter()
+            .map(|coord| coord.y)
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, [3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn parse_documented_example() {
+        let set = PieceSet::interned(
+            "
+            # comments start with '#'
+            piece I
+            color cyan
+            kicks srs-i
+            center 0.5,-0.5
+            spawn -1,0
+            cells -1,0 0,0 1,0 2,0
+            ",
+        )
+        .expect("Should be a valid piece set");
+
+        let parsed = set.kind('I').expect("Should have an I");
+        let builtin = Kind::I;
+        assert_eq!(parsed.0.rotation_center, builtin.0.rotation_center);
+        assert_eq!(parsed.0.spawn_offset, builtin.0.spawn_offset);
+        assert_eq!(parsed.cells(), builtin.cells());
+    }
+
+    #[test]
+    fn definitions_are_parsed_once() {
+        let first = PieceSet::interned(PENTOMINOES);
+        let second = PieceSet::interned(PENTOMINOES);
+        assert!(std::ptr::eq(first.unwrap(), second.unwrap()));
+        assert_eq!(
+            PieceSet::interned("piece X").err(),
+            Some(ParsePieceSetError::MissingCells('X'))
+        );
+    }
+
+    #[test]
+    fn tetrominoes_are_distinct_from_trominoes() {
+        assert_ne!(
+            PieceSet::TETROMINOES.kind('I'),
+            PieceSet::TROMINOES.kind('I')
+        );
+        assert_eq!(PieceSet::TETROMINOES.kind('T'), Some(Kind::T));
+    }
+
+    #[test]
+    fn invalid_piece_sets() {
+        assert_eq!(
+            "".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::Empty)
+        );
+        assert_eq!(
+            "color red".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::MissingPiece { line: 0 })
+        );
+        assert_eq!(
+            "piece X\ncenter 0.5,0".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::InvalidValue { line: 1 })
+        );
+        assert_eq!(
+            "piece X\nshape round".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::UnknownKey { line: 1 })
+        );
+        assert_eq!(
+            "piece X\npiece X".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::DuplicateLetter('X'))
+        );
+        assert_eq!(
+            "piece X\ncolor red".parse::<PieceSet>().err(),
+            Some(ParsePieceSetError::MissingCells('X'))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Where new pieces appear. Columns come from the piece definitions, the
+//! rows depend on the ruleset.
+
+use cgmath::Zero;
+
+use super::Offset;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpawnRule {
+    /// Pieces spawn in rows 21 and 22, above the visible matrix, and
+    /// immediately drop one row if nothing is in the way.
+    #[default]
+    Guideline,
+    /// Pieces spawn in the top two rows of the matrix, like in TGM.
+    InField,
+}
+
+impl SpawnRule {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "guideline" => Some(Self::Guideline),
+            "tgm" => Some(Self::InField),
+            _ => None,
+        }
+    }
+
+    /// Offset, in blocks, from the buffer zone spawn of [`Piece::sized`].
+    ///
+    /// [`Piece::sized`]: super::piece::Piece::sized
+    pub(super) fn offset(&self) -> Offset {
+        match self {
+            Self::Guideline => Offset::zero(),
+            Self::InField => Offset::new(0, -2),
+        }
+    }
+
+    pub(super) fn drops_on_spawn(&self) -> bool {
+        matches!(self, Self::Guideline)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::engine::{Engine, PieceKind};
+
+    fn spawned(rule: SpawnRule, kind: PieceKind) -> (Vec<usize>, Vec<usize>) {
+        spawned_sized(rule, kind, 1)
+    }
+
+    fn spawned_sized(
+        rule: SpawnRule,
+        kind: PieceKind,
+        block_size: usize,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let mut engine = Engine::new()
+            .with_spawn_rule(rule)
+            .with_block_size(block_size);
+        engine.spawn(kind);
+        let (cells, _, _, _) = engine.cursor_info().expect("Should spawn");
+
+        let mut columns = cells.iter().map(|coord| coord.x).collect::<Vec<_>>();
+        let mut rows = cells.iter().map(|coord| coord.y).collect::<Vec<_>>();
+        columns.sort();
+        columns.dedup();
+        rows.sort();
+        rows.dedup();
+        (columns, rows)
+    }
+
+    #[rstest]
+    #[case(PieceKind::I, &[3, 4, 5, 6])]
+    #[case(PieceKind::O, &[4, 5])]
+    #[case(PieceKind::T, &[3, 4, 5])]
+    #[case(PieceKind::S, &[3, 4, 5])]
+    #[case(PieceKind::Z, &[3, 4, 5])]
+    #[case(PieceKind::J, &[3, 4, 5])]
+    #[case(PieceKind::L, &[3, 4, 5])]
+    fn spawn_columns(#[case] kind: PieceKind, #[case] expected: &[usize]) {
+        let (columns, _) = spawned(SpawnRule::Guideline, kind);
+        assert_eq!(columns, expected);
+    }
+
+    //NOTE: the field is 5 blocks wide, 3 wide pieces fit in the middle
+    #[rstest]
+    #[case(PieceKind::T)]
+    #[case(PieceKind::S)]
+    #[case(PieceKind::Z)]
+    #[case(PieceKind::J)]
+    #[case(PieceKind::L)]
+    fn big_spawn_columns(#[case] kind: PieceKind) {
+        let (columns, _) = spawned_sized(SpawnRule::Guideline, kind, 2);
+        assert_eq!(columns, (2..8).collect::<Vec<_>>());
+    }
+
+    #[rstest]
+    #[case(SpawnRule::Guideline, PieceKind::T, &[19, 20])]
+    #[case(SpawnRule::Guideline, PieceKind::I, &[19])]
+    #[case(SpawnRule::InField, PieceKind::T, &[18, 19])]
+    #[case(SpawnRule::InField, PieceKind::I, &[18])]
+    fn spawn_rows(
+        #[case] rule: SpawnRule,
+        #[case] kind: PieceKind,
+        #[case] expected: &[usize],
+    ) {
+        let (_, rows) = spawned(rule, kind);
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn no_spawn_drop_when_blocked() {
+        let board = format!("...G......\n{}", "..........\n".repeat(19));
+        let mut engine = board.parse::<Engine>().expect("Should be valid");
+        engine.spawn(PieceKind::T);
+
+        let (cells, _, _, _) = engine.cursor_info().expect("Should spawn");
+        assert!(cells.iter().all(|coord| coord.y >= 20));
+    }
+}
@@ -0,0 +1,112 @@
+//! Invisible and fading stack modes. The board is always fully simulated,
+//! these only decide how much of the locked stack is shown.
+
+use std::time::Duration;
+
+/// How locked cells are shown once they become part of the stack.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StackVisibility {
+    #[default]
+    Visible,
+    /// Cells start fading out once they have been locked for this long.
+    Fading(Duration),
+    /// Cells vanish as soon as they lock.
+    Invisible,
+}
+
+impl StackVisibility {
+    /// How long a fading cell takes to go from fully shown to hidden.
+    pub const FADE_OUT_TIME: Duration = Duration::from_millis(250);
+    /// How long the whole stack is shown after a line clear.
+    pub const REVEAL_TIME: Duration = Duration::from_secs(1);
+
+    /// Opacity, from `0.0` to `1.0`, of a cell that was locked `age` ago.
+    pub(super) fn opacity(&self, age: Duration) -> f32 {
+        match *self {
+            Self::Visible => 1.0,
+            Self::Invisible => 0.0,
+            Self::Fading(delay) => {
+                let fading = age.saturating_sub(delay).as_secs_f32();
+                1.0 - (fading / Self::FADE_OUT_TIME.as_secs_f32()).min(1.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::engine::{Coordinate, Engine};
+
+    #[rstest]
+    #[case(StackVisibility::Visible, 10_000, 1.0)]
+    #[case(StackVisibility::Invisible, 0, 0.0)]
+    #[case(StackVisibility::Fading(Duration::from_secs(1)), 500, 1.0)]
+    #[case(StackVisibility::Fading(Duration::from_secs(1)), 1125, 0.5)]
+    #[case(StackVisibility::Fading(Duration::from_secs(1)), 2000, 0.0)]
+    fn opacity(
+        #[case] visibility: StackVisibility,
+        #[case] age_ms: u64,
+        #[case] expected: f32,
+    ) {
+        let age = Duration::from_millis(age_ms);
+        assert_eq!(visibility.opacity(age), expected);
+    }
+
+    fn shown(engine: &Engine, coord: Coordinate) -> bool {
+        engine
+            .visible_cells()
+            .any(|(at, cell)| at == coord && cell.is_some())
+    }
+
+    #[test]
+    fn cells_fade_from_their_lock_time() {
+        let mut engine = "
+            ....t.....
+            ...ttt....
+            G.........
+        "
+        .parse::<Engine>()
+        .expect("Should be a valid board")
+        .with_stack_visibility(StackVisibility::Fading(Duration::from_secs(1)));
+
+        engine.advance_clock(Duration::from_secs(2));
+        engine.hard_drop();
+        engine.place_cursor();
+
+        let garbage = Coordinate::new(0, 0);
+        let placed = Coordinate::new(3, 0);
+        assert!(!shown(&engine, garbage));
+        assert!(shown(&engine, placed));
+
+        engine.advance_clock(Duration::from_secs(2));
+        assert!(!shown(&engine, placed));
+    }
+
+    #[test]
+    fn line_clear_reveals_stack() {
+        let mut engine: Engine = "
+            ......iiii
+            GGGGGG....
+            GGGGGGGG.G
+        "
+        .parse::<Engine>()
+        .expect("Should be a valid board")
+        .with_stack_visibility(StackVisibility::Invisible);
+
+        engine.hard_drop();
+        engine.place_cursor();
+        assert!(!shown(&engine, Coordinate::new(0, 0)));
+
+        let lines = engine.full_lines();
+        assert_eq!(lines, [1]);
+        engine.clear_lines(&lines);
+        assert!(shown(&engine, Coordinate::new(0, 0)));
+
+        engine.advance_clock(StackVisibility::REVEAL_TIME);
+        assert!(!shown(&engine, Coordinate::new(0, 0)));
+    }
+}
@@ -0,0 +1,475 @@
+//! What a frontend has to provide to play the game: a [`Renderer`] to
+//! draw it and an [`InputSource`] to control it. The game loop and the
+//! layout of a frame are shared by every frontend.
+
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+use crate::{
+    engine::{Color, Coordinate, Offset},
+    game::{DeltaTime, Game, InputAction, State, TimedInput},
+    replay::Replay,
+};
+
+/// Longest frame the simulation catches up on, so a stalled frontend does
+/// not run the game in fast forward afterwards.
+pub const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+const REPLAY_DIR: &str = "replays";
+
+/// A matrix cell as drawn, with the color and opacity of its block.
+pub type VisibleCell = (Coordinate, Option<(Color, f32)>);
+
+/// Areas around the matrix, laid out by each frontend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Panel {
+    Hold,
+    Next,
+    Score,
+}
+
+/// Draws a frame of the game, see [`draw_game`] for the order of calls.
+pub trait Renderer {
+    /// Starts a new frame.
+    fn clear(&mut self);
+    /// Draws the locked cells. In big mode, each block covers a square of
+    /// `block_size` cells.
+    fn draw_matrix(&mut self, cells: &[VisibleCell], block_size: usize);
+    /// Draws full lines about to be cleared, `progress` going from 0 to 1.
+    fn draw_cleared_lines(&mut self, lines: &[usize], progress: f32);
+    fn draw_piece(&mut self, cells: &[Coordinate], color: Color);
+    /// Draws where the piece would land, under the piece itself.
+    fn draw_ghost(&mut self, cells: &[Coordinate], color: Color);
+    /// Draws pieces outside of the matrix, in order, like the held ones.
+    fn draw_panel(&mut self, panel: Panel, pieces: &[(Vec<Offset>, Color)]);
+    /// Writes lines of text in a panel.
+    fn draw_text(&mut self, panel: Panel, text: &str);
+    /// Draws over the matrix what the state calls for, like the countdown.
+    fn draw_state(&mut self, state: &State, progress: f32);
+    /// Shows the frame.
+    fn present(&mut self);
+}
+
+/// Requests to the frontend rather than to the game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    /// Exports the fumen of the board.
+    ExportBoard,
+    /// Exports the fumen of the whole game.
+    ExportHistory,
+}
+
+/// Something the player should hear of, reported by the frontend once its
+/// screen allows it.
+#[derive(Debug)]
+pub enum Report {
+    /// An exported fumen.
+    Fumen(String),
+    /// Where the replay was saved, or why it could not be.
+    ReplaySaved(io::Result<PathBuf>),
+}
+
+/// What came of a frame.
+#[derive(Debug)]
+pub struct Frame {
+    /// Whether to go on with the next frame.
+    pub go_on: bool,
+    pub reports: Vec<Report>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontendEvent {
+    /// A key of the game, pressed or released `age` ago.
+    Input {
+        action: InputAction,
+        age: Duration,
+    },
+    Command(Command),
+}
+
+pub trait InputSource {
+    /// Events since the last poll, oldest first.
+    fn poll(&mut self) -> Vec<FrontendEvent>;
+}
+
+impl Report {
+    /// Whether the report is of something that went wrong.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::ReplaySaved(Err(_)))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fumen(fumen) => write!(f, "{fumen}"),
+            Self::ReplaySaved(Ok(path)) => {
+                write!(f, "Replay saved to {}", path.display())
+            }
+            Self::ReplaySaved(Err(error)) => {
+                write!(f, "Replay could not be saved: {error}")
+            }
+        }
+    }
+}
+
+/// Draws the game, leaving the frame to present.
+pub fn draw_game(renderer: &mut impl Renderer, game: &Game) {
+    let engine = &game.engine;
+    renderer.clear();
+
+    let cells = engine.visible_cells().collect::<Vec<_>>();
+    renderer.draw_matrix(&cells, engine.block_size());
+    if let Some((lines, progress)) = game.line_clear_progress() {
+        renderer.draw_cleared_lines(lines, progress);
+    }
+    if let Some((cells, color, ..)) = engine.cursor_info() {
+        if let Some(ghost) = engine.ghost_cells() {
+            renderer.draw_ghost(&ghost, color);
+        }
+        renderer.draw_piece(&cells, color);
+    }
+    renderer.draw_state(game.state(), game.state_progress());
+
+    renderer.draw_panel(Panel::Hold, &engine.held_cursor_info());
+    renderer.draw_panel(Panel::Next, &engine.next_pieces());
+    renderer.draw_text(
+        Panel::Score,
+        &format!(
+            "SCORE\n{}\nLINES\n{}\nLEVEL\n{}",
+            engine.score(),
+            engine.lines(),
+            engine.level()
+        ),
+    );
+}
+
+/// Plays `game` in fixed ticks, as many as real time calls for, and
+/// records it in `replay`, saved when the game ends or the player quits.
+pub struct GameLoop {
+    game: Game,
+    replay: Replay,
+    accumulator: Duration,
+    saved: bool,
+    replay_dir: PathBuf,
+}
+
+impl GameLoop {
+    pub fn new(game: Game, replay: Replay) -> Self {
+        Self {
+            game,
+            replay,
+            accumulator: Duration::ZERO,
+            saved: false,
+            replay_dir: PathBuf::from(REPLAY_DIR),
+        }
+    }
+
+    /// Saves the replay in `dir` rather than in `replays`.
+    pub fn with_replay_dir(self, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            replay_dir: dir.into(),
+            ..self
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Runs until the player quits, handing each report to `report` as
+    /// soon as it is made.
+    pub fn run(
+        &mut self,
+        renderer: &mut impl Renderer,
+        input: &mut impl InputSource,
+        mut report: impl FnMut(Report),
+    ) {
+        let mut delta_time = DeltaTime::new();
+        loop {
+            delta_time.update();
+            let frame = self.frame(renderer, input, delta_time.get());
+            frame.reports.into_iter().for_each(&mut report);
+            if !frame.go_on {
+                return;
+            }
+        }
+    }
+
+    /// Plays and draws a frame that took `elapsed`.
+    pub fn frame(
+        &mut self,
+        renderer: &mut impl Renderer,
+        input: &mut impl InputSource,
+        elapsed: Duration,
+    ) -> Frame {
+        let mut reports = Vec::new();
+        let tick_rate = self.game.tick_rate();
+        let tick_length = tick_rate.tick_length();
+        self.accumulator += elapsed.min(MAX_FRAME_TIME);
+
+        //NOTE: inputs are placed on the tick they happened at, counting
+        //      back from the end of the frame by their age
+        let ticks =
+            (self.accumulator.as_nanos() / tick_length.as_nanos()) as u64;
+        let first_tick = self.game.tick();
+        let last_tick = first_tick + ticks;
+
+        for event in input.poll() {
+            match event {
+                FrontendEvent::Input { action, age } => {
+                    self.game.queue_input(TimedInput {
+                        tick: last_tick
+                            .saturating_sub(tick_rate.ticks(age).into())
+                            .max(first_tick),
+                        action,
+                    })
+                }
+                FrontendEvent::Command(Command::Quit) => {
+                    if !self.saved {
+                        reports.push(self.save_replay());
+                    }
+                    return Frame {
+                        go_on: false,
+                        reports,
+                    };
+                }
+                FrontendEvent::Command(Command::ExportBoard) => {
+                    reports.push(Report::Fumen(self.game.engine.to_fumen()))
+                }
+                FrontendEvent::Command(Command::ExportHistory) => reports
+                    .push(Report::Fumen(self.game.engine.history_to_fumen())),
+            }
+        }
+
+        for _ in 0..ticks {
+            self.game.step();
+        }
+        self.accumulator -= tick_length * ticks as u32;
+
+        if *self.game.state() == State::GameOver && !self.saved {
+            reports.push(self.save_replay());
+            self.saved = true;
+        }
+        draw_game(renderer, &self.game);
+        renderer.present();
+        Frame {
+            go_on: true,
+            reports,
+        }
+    }
+
+    fn save_replay(&mut self) -> Report {
+        self.replay.record(&self.game);
+        Report::ReplaySaved(self.replay.save(&self.replay_dir))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::{
+        engine::{Engine, Matrix, MoveKind},
+        game::{Handling, Input, KeyAction},
+        ruleset::Ruleset,
+    };
+
+    /// Hands out the events of each frame in turn.
+    struct ScriptedInput(Vec<Vec<FrontendEvent>>);
+
+    impl InputSource for ScriptedInput {
+        fn poll(&mut self) -> Vec<FrontendEvent> {
+            match self.0.is_empty() {
+                true => Vec::new(),
+                false => self.0.remove(0),
+            }
+        }
+    }
+
+    /// Counts frames and keeps the last piece and text drawn.
+    #[derive(Default)]
+    struct RecordingRenderer {
+        frames: usize,
+        piece: Vec<Coordinate>,
+        text: String,
+    }
+
+    impl Renderer for RecordingRenderer {
+        fn clear(&mut self) {
+            self.piece.clear();
+        }
+        fn draw_matrix(&mut self, _: &[VisibleCell], _: usize) {}
+        fn draw_cleared_lines(&mut self, _: &[usize], _: f32) {}
+        fn draw_piece(&mut self, cells: &[Coordinate], _: Color) {
+            self.piece = cells.to_vec();
+        }
+        fn draw_ghost(&mut self, _: &[Coordinate], _: Color) {}
+        fn draw_panel(&mut self, _: Panel, _: &[(Vec<Offset>, Color)]) {}
+        fn draw_text(&mut self, _: Panel, text: &str) {
+            self.text = text.to_owned();
+        }
+        fn draw_state(&mut self, _: &State, _: f32) {}
+        fn present(&mut self) {
+            self.frames += 1;
+        }
+    }
+
+    fn game_loop() -> GameLoop {
+        let replay = Replay::new(Ruleset::default(), Handling::default(), 3);
+        let game = Game::new(Engine::new().with_seed(replay.seed));
+        GameLoop::new(game, replay)
+    }
+
+    fn input(input: Input, action: KeyAction, age: Duration) -> FrontendEvent {
+        FrontendEvent::Input {
+            action: InputAction::new(input, action),
+            age,
+        }
+    }
+
+    #[test]
+    fn frames_run_the_ticks_of_their_time() {
+        let mut game_loop = game_loop();
+        let mut renderer = RecordingRenderer::default();
+        let mut input = ScriptedInput(Vec::new());
+        let tick = game_loop.game.tick_rate().tick_length();
+
+        assert!(
+            game_loop
+                .frame(&mut renderer, &mut input, tick * 3 / 2)
+                .go_on
+        );
+        assert_eq!(game_loop.game().tick(), 1);
+        assert!(game_loop.frame(&mut renderer, &mut input, tick / 2).go_on);
+        assert_eq!(game_loop.game().tick(), 2);
+        assert!(
+            game_loop
+                .frame(&mut renderer, &mut input, Duration::MAX)
+                .go_on
+        );
+        assert_eq!(
+            game_loop.game().tick(),
+            2 + game_loop.game.tick_rate().ticks(MAX_FRAME_TIME) as u64
+        );
+
+        assert_eq!(renderer.frames, 3);
+        assert!(!renderer.piece.is_empty());
+        assert!(renderer.text.starts_with("SCORE\n0\n"));
+    }
+
+    #[test]
+    fn inputs_are_placed_by_their_age() {
+        let mut game_loop = game_loop();
+        let mut renderer = RecordingRenderer::default();
+        let tick = game_loop.game.tick_rate().tick_length();
+        let mut input = ScriptedInput(vec![vec![
+            input(Input::Move(MoveKind::Left), KeyAction::Press, tick * 3),
+            input(Input::Move(MoveKind::Left), KeyAction::Release, tick),
+        ]]);
+
+        game_loop.frame(&mut renderer, &mut input, tick * 4);
+        let handled = game_loop.game().handled_inputs();
+        assert_eq!(
+            handled.iter().map(|input| input.tick).collect::<Vec<_>>(),
+            [1, 3]
+        );
+    }
+
+    /// An empty directory of its own for the replays of a test.
+    fn replay_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("totris-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Asserts that the reports are only the replay, saved in `dir`.
+    fn assert_replay_saved(reports: &[Report], dir: &Path) {
+        let [Report::ReplaySaved(Ok(path))] = reports else {
+            panic!("Should only report the saved replay: {reports:?}");
+        };
+        assert_eq!(path.parent(), Some(dir));
+        assert!(Replay::load(path.to_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn quitting_ends_the_loop() {
+        let dir = replay_dir("quitting_ends_the_loop");
+        let mut game_loop = game_loop().with_replay_dir(&dir);
+        let mut renderer = RecordingRenderer::default();
+        let mut input = ScriptedInput(vec![
+            vec![input(Input::HardDrop, KeyAction::Press, Duration::ZERO)],
+            vec![FrontendEvent::Command(Command::Quit)],
+        ]);
+
+        let frame = game_loop.frame(&mut renderer, &mut input, Duration::ZERO);
+        assert!(frame.go_on);
+        assert!(frame.reports.is_empty());
+        let frame = game_loop.frame(&mut renderer, &mut input, Duration::ZERO);
+        assert!(!frame.go_on);
+        assert_replay_saved(&frame.reports, &dir);
+        assert_eq!(renderer.frames, 1);
+    }
+
+    #[test]
+    fn game_over_saves_the_replay_once() {
+        let dir = replay_dir("game_over_saves_the_replay_once");
+        let stack = "GGGGGGGGG.\n".repeat(Matrix::HEIGHT - 1);
+        let engine = stack
+            .parse::<Engine>()
+            .expect("Should be a valid board")
+            .with_seed(3);
+        let replay = Replay::new(Ruleset::default(), Handling::default(), 3);
+        let mut game_loop =
+            GameLoop::new(Game::new(engine), replay).with_replay_dir(&dir);
+        let mut renderer = RecordingRenderer::default();
+        let tick = game_loop.game.tick_rate().tick_length();
+        let mut input = ScriptedInput(vec![
+            vec![input(Input::HardDrop, KeyAction::Press, tick)],
+            vec![FrontendEvent::Command(Command::Quit)],
+        ]);
+
+        let frame = game_loop.frame(&mut renderer, &mut input, tick * 2);
+        assert_eq!(*game_loop.game().state(), State::GameOver);
+        assert!(frame.go_on);
+        assert_replay_saved(&frame.reports, &dir);
+        let frame = game_loop.frame(&mut renderer, &mut input, Duration::ZERO);
+        assert!(!frame.go_on);
+        assert!(frame.reports.is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn exports_are_reported() {
+        let mut game_loop = game_loop();
+        let mut renderer = RecordingRenderer::default();
+        let mut input = ScriptedInput(vec![vec![
+            FrontendEvent::Command(Command::ExportBoard),
+            FrontendEvent::Command(Command::ExportHistory),
+        ]]);
+
+        let frame = game_loop.frame(&mut renderer, &mut input, Duration::ZERO);
+        let engine = &game_loop.game().engine;
+        assert!(matches!(
+            frame.reports.as_slice(),
+            [Report::Fumen(board), Report::Fumen(history)]
+                if *board == engine.to_fumen()
+                    && *history == engine.history_to_fumen()
+        ));
+    }
+
+    #[test]
+    fn reports_are_displayed() {
+        let saved = Report::ReplaySaved(Ok(PathBuf::from("replays/1.totr")));
+        let failed = Report::ReplaySaved(Err(io::ErrorKind::NotFound.into()));
+
+        assert_eq!(saved.to_string(), "Replay saved to replays/1.totr");
+        assert!(!saved.is_error());
+        assert_eq!(
+            failed.to_string(),
+            "Replay could not be saved: entity not found"
+        );
+        assert!(failed.is_error());
+    }
+}
@@ -0,0 +1,288 @@
+use crate::engine::{Matrix, MoveKind};
+
+use super::{Handling, SocdPolicy, TickRate};
+
+/// Shifts the piece on its own while a move key is held, after the DAS
+/// and then at the ARR. Times are counted in ticks.
+#[derive(Clone, Default)]
+pub struct AutoShift {
+    das: u32,
+    arr: u32,
+    dcd: u32,
+    socd: SocdPolicy,
+
+    /// Move keys held down, in the order they were pressed.
+    held: Vec<MoveKind>,
+    /// Direction picked from the held keys by the SOCD policy.
+    current_move: Option<MoveKind>,
+    /// How long the current direction has been held.
+    charge: u32,
+    /// Ticks left until the next shift.
+    next_shift: u32,
+    /// Shifts from the last tick, not applied to the piece yet.
+    shifts: usize,
+}
+
+impl AutoShift {
+    pub fn new(handling: &Handling, tick_rate: TickRate) -> Self {
+        Self {
+            das: tick_rate.ticks(handling.das),
+            arr: tick_rate.ticks(handling.arr),
+            dcd: tick_rate.ticks(handling.dcd),
+            socd: handling.socd,
+            ..Default::default()
+        }
+    }
+
+    /// Direction the piece shifts in, if any.
+    pub fn direction(&self) -> Option<MoveKind> {
+        self.current_move
+    }
+
+    /// Whether the DAS ran out and the piece is auto shifting.
+    pub fn is_charged(&self) -> bool {
+        self.current_move.is_some() && self.charge >= self.das
+    }
+
+    /// A move key was pressed. Whether its direction takes over depends
+    /// on the SOCD policy.
+    pub fn press(&mut self, kind: MoveKind) {
+        if !self.held.contains(&kind) {
+            self.held.push(kind);
+        }
+        //NOTE: pressing the current direction again charges it anew
+        if self.current_move == Some(kind) {
+            self.restart();
+        }
+        self.resolve();
+    }
+
+    /// A move key was released, the other one may take over again if it
+    /// is still held.
+    pub fn release(&mut self, kind: MoveKind) {
+        self.held.retain(|held| *held != kind);
+        self.resolve();
+    }
+
+    /// Picks the direction from the held keys, restarting the DAS when it
+    /// changes.
+    fn resolve(&mut self) {
+        let direction = match (self.socd, self.held.as_slice()) {
+            (_, []) => None,
+            (_, [only]) => Some(*only),
+            (SocdPolicy::LastInput, [.., last]) => Some(*last),
+            (SocdPolicy::FirstInput, [first, ..]) => Some(*first),
+            (SocdPolicy::Neutral, _) => None,
+        };
+        if direction != self.current_move {
+            self.current_move = direction;
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.charge = 0;
+        self.next_shift = self.das;
+        self.shifts = 0;
+    }
+
+    /// Throws the DAS charge away, as if the key was pressed again.
+    pub fn cancel(&mut self) {
+        if self.current_move.is_some() {
+            self.restart();
+        }
+    }
+
+    /// Pauses auto shifting for the DAS cut delay, if it is going on.
+    pub fn pause(&mut self) {
+        if self.is_charged() && self.dcd > 0 {
+            self.next_shift = self.next_shift.max(self.dcd);
+            self.shifts = 0;
+        }
+    }
+
+    /// Cells the piece shifts by, taken once per tick. An ARR of zero
+    /// shifts it across the whole matrix.
+    pub fn take_shifts(&mut self) -> usize {
+        std::mem::take(&mut self.shifts)
+    }
+
+    /// Runs a tick of the DAS and ARR. Shifts not taken before the next
+    /// tick, as when no piece is in play, are lost.
+    pub fn tick(&mut self) {
+        self.shifts = self.charged_shifts();
+    }
+
+    fn charged_shifts(&mut self) -> usize {
+        if self.current_move.is_none() {
+            return 0;
+        }
+        self.charge = self.charge.saturating_add(1);
+
+        let mut ticks = 1;
+        let mut shifts = 0;
+        while ticks >= self.next_shift {
+            if self.arr == 0 {
+                self.next_shift = 0;
+                return Matrix::WIDTH;
+            }
+            ticks -= self.next_shift;
+            self.next_shift = self.arr;
+            shifts += 1;
+        }
+        self.next_shift -= ticks;
+        shifts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    const TICK_RATE: TickRate = TickRate(100);
+
+    /// Auto shift with times in ticks.
+    fn auto_shift(das: u32, arr: u32, dcd: u32, socd: SocdPolicy) -> AutoShift {
+        let tick = TICK_RATE.tick_length();
+        let handling = Handling {
+            das: tick * das,
+            arr: tick * arr,
+            dcd: tick * dcd,
+            socd,
+            ..Default::default()
+        };
+        AutoShift::new(&handling, TICK_RATE)
+    }
+
+    /// Shifts for each of `ticks` ticks.
+    fn shifts(auto_shift: &mut AutoShift, ticks: usize) -> Vec<usize> {
+        (0..ticks)
+            .map(|_| {
+                auto_shift.tick();
+                auto_shift.take_shifts()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shifts_after_das_at_arr() {
+        let mut auto_shift = auto_shift(3, 2, 0, SocdPolicy::LastInput);
+        auto_shift.press(MoveKind::Left);
+
+        assert_eq!(shifts(&mut auto_shift, 8), [0, 0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(shifts(&mut auto_shift, 4), [1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn zero_arr_shifts_to_the_wall() {
+        let mut auto_shift = auto_shift(2, 0, 0, SocdPolicy::LastInput);
+        auto_shift.press(MoveKind::Right);
+
+        assert_eq!(
+            shifts(&mut auto_shift, 3),
+            [0, Matrix::WIDTH, Matrix::WIDTH]
+        );
+    }
+
+    #[test]
+    fn das_cut_delay_pauses_shifting() {
+        let mut auto_shift = auto_shift(1, 1, 3, SocdPolicy::LastInput);
+        auto_shift.press(MoveKind::Right);
+        assert_eq!(shifts(&mut auto_shift, 2), [1, 1]);
+
+        auto_shift.pause();
+        assert_eq!(shifts(&mut auto_shift, 4), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn releasing_goes_back_to_held_direction() {
+        let mut auto_shift = auto_shift(1, 1, 0, SocdPolicy::LastInput);
+        auto_shift.press(MoveKind::Left);
+        auto_shift.press(MoveKind::Right);
+        assert_eq!(auto_shift.direction(), Some(MoveKind::Right));
+
+        auto_shift.release(MoveKind::Right);
+        assert_eq!(auto_shift.direction(), Some(MoveKind::Left));
+        assert!(!auto_shift.is_charged());
+
+        auto_shift.release(MoveKind::Left);
+        assert_eq!(auto_shift.direction(), None);
+        assert_eq!(shifts(&mut auto_shift, 1), [0]);
+    }
+
+    /// Runs `events`, like `L+` to press left or `R-` to release right,
+    /// and gives the direction after each one: `L`, `R` or `.` for none.
+    fn directions(policy: SocdPolicy, events: &str) -> String {
+        let mut auto_shift = auto_shift(1, 1, 0, policy);
+        events
+            .split_whitespace()
+            .map(|event| {
+                let kind = match &event[..1] {
+                    "L" => MoveKind::Left,
+                    _ => MoveKind::Right,
+                };
+                match &event[1..] {
+                    "+" => auto_shift.press(kind),
+                    _ => auto_shift.release(kind),
+                }
+                match auto_shift.direction() {
+                    Some(MoveKind::Left) => 'L',
+                    Some(MoveKind::Right) => 'R',
+                    None => '.',
+                }
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case(SocdPolicy::LastInput, "L+ L- R+ R-", "L.R.")]
+    #[case(SocdPolicy::LastInput, "L+ R+ L- R-", "LRR.")]
+    #[case(SocdPolicy::LastInput, "L+ R+ R- L-", "LRL.")]
+    #[case(SocdPolicy::LastInput, "R+ R- L+ L-", "R.L.")]
+    #[case(SocdPolicy::LastInput, "R+ L+ R- L-", "RLL.")]
+    #[case(SocdPolicy::LastInput, "R+ L+ L- R-", "RLR.")]
+    #[case(SocdPolicy::FirstInput, "L+ L- R+ R-", "L.R.")]
+    #[case(SocdPolicy::FirstInput, "L+ R+ L- R-", "LLR.")]
+    #[case(SocdPolicy::FirstInput, "L+ R+ R- L-", "LLL.")]
+    #[case(SocdPolicy::FirstInput, "R+ R- L+ L-", "R.L.")]
+    #[case(SocdPolicy::FirstInput, "R+ L+ R- L-", "RRL.")]
+    #[case(SocdPolicy::FirstInput, "R+ L+ L- R-", "RRR.")]
+    #[case(SocdPolicy::Neutral, "L+ L- R+ R-", "L.R.")]
+    #[case(SocdPolicy::Neutral, "L+ R+ L- R-", "L.R.")]
+    #[case(SocdPolicy::Neutral, "L+ R+ R- L-", "L.L.")]
+    #[case(SocdPolicy::Neutral, "R+ R- L+ L-", "R.L.")]
+    #[case(SocdPolicy::Neutral, "R+ L+ R- L-", "R.L.")]
+    #[case(SocdPolicy::Neutral, "R+ L+ L- R-", "R.R.")]
+    fn socd(
+        #[case] policy: SocdPolicy,
+        #[case] events: &str,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(directions(policy, events), expected);
+    }
+
+    #[test]
+    fn losing_direction_does_not_restart_das() {
+        let mut auto_shift = auto_shift(1, 1, 0, SocdPolicy::FirstInput);
+        auto_shift.press(MoveKind::Left);
+        assert_eq!(shifts(&mut auto_shift, 1), [1]);
+
+        auto_shift.press(MoveKind::Right);
+        auto_shift.release(MoveKind::Right);
+        assert!(auto_shift.is_charged());
+    }
+
+    #[test]
+    fn cancel_restarts_the_das() {
+        let mut auto_shift = auto_shift(2, 1, 0, SocdPolicy::LastInput);
+        auto_shift.press(MoveKind::Left);
+        assert_eq!(shifts(&mut auto_shift, 3), [0, 1, 1]);
+
+        auto_shift.tick();
+        auto_shift.cancel();
+        assert_eq!(auto_shift.take_shifts(), 0);
+        assert_eq!(shifts(&mut auto_shift, 3), [0, 1, 1]);
+    }
+}
@@ -0,0 +1,323 @@
+//! Handling settings, tuned by each player rather than by the ruleset.
+//!
+//! They are loaded from a settings file with one setting per line, times
+//! in milliseconds. Missing settings keep their default:
+//!
+//! ```text
+//! # setting  value
+//! das        133
+//! arr        0      # pieces teleport to the wall
+//! sdf        instant
+//! dcd        17
+//! rotate_cut on     # rotating cancels DAS
+//! socd       neutral
+//! irs        on     # rotate and hold keys apply to new pieces
+//! ```
+
+use std::{fmt, str::FromStr, time::Duration};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Handling {
+    /// Delayed Auto Shift: how long a move key is held before the piece
+    /// starts shifting on its own.
+    pub das: Duration,
+    /// Auto Repeat Rate: time between auto shifts. Zero shifts the piece
+    /// to the wall at once.
+    pub arr: Duration,
+    pub sdf: SoftDropFactor,
+    /// DAS Cut Delay: auto shifting pauses this long after a rotation or
+    /// a new piece.
+    pub dcd: Duration,
+    /// A DAS charged when a piece locks carries over to the next one, if
+    /// the key is still held. Otherwise it restarts when the piece spawns.
+    pub preserve_charge: bool,
+    /// Rotating throws the DAS charge away.
+    pub rotate_cut: bool,
+    /// Hard dropping throws the DAS charge away.
+    pub drop_cut: bool,
+    /// DAS keeps charging while cleared lines are shown.
+    pub line_clear_charge: bool,
+    pub socd: SocdPolicy,
+    /// Initial Rotation and Hold Systems: a rotate or hold key still held
+    /// when a piece spawns applies to it right away.
+    pub initial_actions: bool,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das: Duration::from_millis(300),
+            arr: Duration::from_millis(35),
+            sdf: SoftDropFactor::Factor(20.0),
+            dcd: Duration::ZERO,
+            preserve_charge: true,
+            rotate_cut: false,
+            drop_cut: false,
+            line_clear_charge: true,
+            socd: SocdPolicy::LastInput,
+            initial_actions: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoftDropFactor {
+    /// Soft drop multiplies gravity by this much.
+    Factor(f32),
+    /// Soft drop takes the piece to the floor, without locking it.
+    Instant,
+}
+
+/// Simultaneous Opposing Cardinal Directions: which way the piece goes
+/// while left and right are both held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SocdPolicy {
+    /// The key pressed last wins, releasing it goes back to the other.
+    #[default]
+    LastInput,
+    /// The key pressed first wins until it is released.
+    FirstInput,
+    /// Both directions cancel out and the piece stays put.
+    Neutral,
+}
+
+impl SocdPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "last" => Some(Self::LastInput),
+            "first" => Some(Self::FirstInput),
+            "neutral" => Some(Self::Neutral),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LastInput => "last",
+            Self::FirstInput => "first",
+            Self::Neutral => "neutral",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseHandlingError {
+    Unreadable(String),
+    InvalidLine { line: usize },
+    UnknownSetting { line: usize },
+}
+
+impl fmt::Display for ParseHandlingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable(path) => write!(f, "cannot read {path}"),
+            Self::InvalidLine { line } => {
+                write!(f, "invalid setting on line {}", line + 1)
+            }
+            Self::UnknownSetting { line } => {
+                write!(f, "unknown setting on line {}", line + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseHandlingError {}
+
+impl Handling {
+    pub fn load(path: &str) -> Result<Self, ParseHandlingError> {
+        std::fs::read_to_string(path)
+            .map_err(|_| ParseHandlingError::Unreadable(path.into()))?
+            .parse()
+    }
+}
+
+impl FromStr for Handling {
+    type Err = ParseHandlingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut handling = Self::default();
+
+        for (index, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = ParseHandlingError::InvalidLine { line: index };
+            let mut words = line.split_whitespace();
+            let (Some(setting), Some(value), None) =
+                (words.next(), words.next(), words.next())
+            else {
+                return Err(invalid);
+            };
+
+            match setting {
+                "das" => handling.das = parse_millis(value).ok_or(invalid)?,
+                "arr" => handling.arr = parse_millis(value).ok_or(invalid)?,
+                "dcd" => handling.dcd = parse_millis(value).ok_or(invalid)?,
+                "preserve_das" => {
+                    handling.preserve_charge =
+                        parse_switch(value).ok_or(invalid)?
+                }
+                "rotate_cut" => {
+                    handling.rotate_cut = parse_switch(value).ok_or(invalid)?
+                }
+                "drop_cut" => {
+                    handling.drop_cut = parse_switch(value).ok_or(invalid)?
+                }
+                "line_clear_das" => {
+                    handling.line_clear_charge =
+                        parse_switch(value).ok_or(invalid)?
+                }
+                "irs" => {
+                    handling.initial_actions =
+                        parse_switch(value).ok_or(invalid)?
+                }
+                "socd" => {
+                    handling.socd =
+                        SocdPolicy::from_name(value).ok_or(invalid)?
+                }
+                "sdf" => {
+                    handling.sdf = match value {
+                        "instant" => SoftDropFactor::Instant,
+                        factor => SoftDropFactor::Factor(
+                            factor
+                                .parse()
+                                .ok()
+                                .filter(|factor: &f32| factor.is_finite())
+                                .filter(|factor| *factor >= 1.0)
+                                .ok_or(invalid)?,
+                        ),
+                    }
+                }
+                _ => {
+                    return Err(ParseHandlingError::UnknownSetting {
+                        line: index,
+                    })
+                }
+            }
+        }
+
+        Ok(handling)
+    }
+}
+
+/// Writes every setting, in the settings file format.
+impl fmt::Display for Handling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |duration: Duration| duration.as_micros() as f64 / 1000.0;
+        let switch = |on: bool| if on { "on" } else { "off" };
+
+        writeln!(f, "das {}", millis(self.das))?;
+        writeln!(f, "arr {}", millis(self.arr))?;
+        match self.sdf {
+            SoftDropFactor::Factor(factor) => writeln!(f, "sdf {factor}")?,
+            SoftDropFactor::Instant => writeln!(f, "sdf instant")?,
+        }
+        writeln!(f, "dcd {}", millis(self.dcd))?;
+        writeln!(f, "preserve_das {}", switch(self.preserve_charge))?;
+        writeln!(f, "rotate_cut {}", switch(self.rotate_cut))?;
+        writeln!(f, "drop_cut {}", switch(self.drop_cut))?;
+        writeln!(f, "line_clear_das {}", switch(self.line_clear_charge))?;
+        writeln!(f, "socd {}", self.socd.name())?;
+        writeln!(f, "irs {}", switch(self.initial_actions))
+    }
+}
+
+/// Parses milliseconds, fractions allowed since handling is often tuned
+/// in frames.
+fn parse_millis(text: &str) -> Option<Duration> {
+    let millis = text.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(millis / 1000.0).ok()
+}
+
+fn parse_switch(text: &str) -> Option<bool> {
+    match text {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let handling: Handling = "
+            # setting  value
+            das        133.5
+            arr        0      # to the wall
+            sdf        instant
+            drop_cut   on
+            socd       first
+            irs        on
+        "
+        .parse()
+        .expect("Should be valid settings");
+
+        assert_eq!(handling.das, Duration::from_micros(133_500));
+        assert_eq!(handling.arr, Duration::ZERO);
+        assert_eq!(handling.sdf, SoftDropFactor::Instant);
+        assert_eq!(handling.dcd, Handling::default().dcd);
+        assert!(handling.drop_cut);
+        assert!(!handling.rotate_cut);
+        assert_eq!(handling.socd, SocdPolicy::FirstInput);
+        assert!(handling.initial_actions);
+        assert_eq!(
+            "sdf 40".parse::<Handling>().map(|handling| handling.sdf),
+            Ok(SoftDropFactor::Factor(40.0))
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let handling = Handling {
+            das: Duration::from_micros(116_700),
+            arr: Duration::ZERO,
+            sdf: SoftDropFactor::Instant,
+            rotate_cut: true,
+            socd: SocdPolicy::Neutral,
+            initial_actions: true,
+            ..Default::default()
+        };
+        assert_eq!(handling.to_string().parse(), Ok(handling));
+        assert_eq!(
+            Handling::default().to_string().parse(),
+            Ok(Handling::default())
+        );
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert_eq!(
+            "das 100\narr".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 1 })
+        );
+        assert_eq!(
+            "das -5".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "arr 1e300".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "dcd NaN".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "sdf 0.5".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "rotate_cut yes".parse::<Handling>(),
+            Err(ParseHandlingError::InvalidLine { line: 0 })
+        );
+        assert_eq!(
+            "\nspeed 9000".parse::<Handling>(),
+            Err(ParseHandlingError::UnknownSetting { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use super::{TickRate, Timer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Guideline: moving or rotating on the ground restarts the delay, at
+    /// most `limit` times. Falling below the lowest row reached so far
+    /// gives the resets back.
+    MoveReset { limit: u32 },
+    /// Only falling a row restarts the delay, like in TGM.
+    StepReset,
+    /// Moving or rotating always restarts the delay.
+    Infinite,
+    /// Classic: the piece locks as soon as it lands.
+    Immediate,
+}
+
+impl LockPolicy {
+    /// Looks up a policy by name: `move` (optionally `move:<limit>`),
+    /// `step`, `infinite` or `immediate`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.split_once(':') {
+            Some(("move", limit)) => Self::MoveReset {
+                limit: limit.parse().ok()?,
+            },
+            None if name == "move" => Self::default(),
+            None if name == "step" => Self::StepReset,
+            None if name == "infinite" => Self::Infinite,
+            None if name == "immediate" => Self::Immediate,
+            _ => return None,
+        })
+    }
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        Self::MoveReset { limit: 15 }
+    }
+}
+
+/// Decides when a piece resting on the stack locks.
+#[derive(Clone)]
+pub struct LockDelay {
+    policy: LockPolicy,
+    /// Steps of `(level, duration)`, each applying from its level until
+    /// the next one.
+    durations: Vec<(u32, Duration)>,
+    timer: Timer,
+    resets_left: u32,
+    /// Lowest row the piece has reached, kicking up and falling back does
+    /// not give resets back.
+    lowest_row: isize,
+    moved: bool,
+}
+
+impl LockDelay {
+    pub const DEFAULT_DURATION: Duration = Duration::from_millis(500);
+
+    pub fn new(policy: LockPolicy, durations: Vec<(u32, Duration)>) -> Self {
+        debug_assert!(durations.is_sorted_by_key(|(level, _)| *level));
+        let mut lock_delay = Self {
+            policy,
+            durations,
+            timer: Timer::default(),
+            resets_left: 0,
+            lowest_row: isize::MAX,
+            moved: false,
+        };
+        lock_delay.reset();
+        lock_delay
+    }
+
+    /// Parses durations in milliseconds, either a single one like `500`
+    /// or steps by level like `0:500,200:400,500:300`.
+    pub fn parse_durations(text: &str) -> Option<Vec<(u32, Duration)>> {
+        let durations = text
+            .split(',')
+            .map(|step| {
+                let (level, millis) = match step.split_once(':') {
+                    Some((level, millis)) => (level.parse().ok()?, millis),
+                    None => (0, step),
+                };
+                Some((level, Duration::from_millis(millis.parse().ok()?)))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        durations
+            .is_sorted_by_key(|(level, _)| *level)
+            .then_some(durations)
+    }
+
+    /// Gets ready for a new piece.
+    pub fn reset(&mut self) {
+        self.timer.reset();
+        self.moved = false;
+        self.lowest_row = isize::MAX;
+        self.resets_left = match self.policy {
+            LockPolicy::MoveReset { limit } => limit,
+            _ => 0,
+        };
+    }
+
+    /// The piece moved or rotated.
+    pub fn moved(&mut self) {
+        self.moved = true;
+    }
+
+    /// The piece fell a row, to `row`.
+    pub fn stepped_down(&mut self, row: isize) {
+        self.timer.reset();
+        if row >= self.lowest_row {
+            return;
+        }
+        self.lowest_row = row;
+        if let LockPolicy::MoveReset { limit } = self.policy {
+            self.resets_left = limit;
+        }
+    }
+
+    /// The piece is not resting on anything.
+    pub fn lift(&mut self) {
+        self.timer.reset();
+        self.moved = false;
+    }
+
+    /// Runs a tick of the delay while the piece rests on the stack,
+    /// returning whether it should lock now.
+    pub fn tick(&mut self, level: u32, tick_rate: TickRate) -> bool {
+        if std::mem::take(&mut self.moved) {
+            match self.policy {
+                LockPolicy::MoveReset { .. } if self.resets_left > 0 => {
+                    self.resets_left -= 1;
+                    self.timer.reset();
+                }
+                LockPolicy::Infinite => self.timer.reset(),
+                _ => {}
+            }
+        }
+
+        if self.policy == LockPolicy::Immediate {
+            return true;
+        }
+        self.timer.set_target(tick_rate.ticks(self.duration(level)));
+        self.timer.tick();
+        self.timer.just_finished()
+    }
+
+    pub fn duration(&self, level: u32) -> Duration {
+        self.durations
+            .iter()
+            .take_while(|(from, _)| *from <= level)
+            .last()
+            .or(self.durations.first())
+            .map_or(Self::DEFAULT_DURATION, |&(_, duration)| duration)
+    }
+}
+
+impl Default for LockDelay {
+    fn default() -> Self {
+        Self::new(LockPolicy::default(), vec![(0, Self::DEFAULT_DURATION)])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    const TICK: Duration = Duration::from_millis(100);
+    const TICK_RATE: TickRate = TickRate(10);
+
+    fn lock_delay(policy: LockPolicy) -> LockDelay {
+        LockDelay::new(policy, vec![(0, TICK * 3)])
+    }
+
+    /// Rests for `ticks`, moving before each one, and returns the tick the
+    /// piece locked on.
+    fn locks_after(lock_delay: &mut LockDelay, ticks: usize) -> Option<usize> {
+        (1..=ticks).find(|_| {
+            lock_delay.moved();
+            lock_delay.tick(1, TICK_RATE)
+        })
+    }
+
+    #[test]
+    fn immediate_locks_on_landing() {
+        let mut lock_delay = lock_delay(LockPolicy::Immediate);
+        assert!(lock_delay.tick(1, TICK_RATE));
+    }
+
+    #[test]
+    fn resting_locks_after_duration() {
+        let mut lock_delay = lock_delay(LockPolicy::StepReset);
+        assert!(!lock_delay.tick(1, TICK_RATE));
+        assert!(!lock_delay.tick(1, TICK_RATE));
+        assert!(lock_delay.tick(1, TICK_RATE));
+    }
+
+    #[rstest]
+    #[case(LockPolicy::MoveReset { limit: 2 }, Some(4))]
+    #[case(LockPolicy::StepReset, Some(3))]
+    #[case(LockPolicy::Infinite, None)]
+    fn moving_on_the_ground(
+        #[case] policy: LockPolicy,
+        #[case] expected: Option<usize>,
+    ) {
+        let mut lock_delay = lock_delay(policy);
+        assert_eq!(locks_after(&mut lock_delay, 100), expected);
+    }
+
+    #[test]
+    fn stepping_down_gives_resets_back() {
+        let mut lock_delay = lock_delay(LockPolicy::MoveReset { limit: 2 });
+        assert_eq!(locks_after(&mut lock_delay, 2), None);
+
+        lock_delay.lift();
+        lock_delay.stepped_down(9);
+        assert_eq!(locks_after(&mut lock_delay, 100), Some(4));
+    }
+
+    #[test]
+    fn falling_back_keeps_resets_used() {
+        let mut lock_delay = lock_delay(LockPolicy::MoveReset { limit: 2 });
+        lock_delay.stepped_down(9);
+        assert_eq!(locks_after(&mut lock_delay, 2), None);
+
+        //NOTE: kicked up a row, then back down to where it rested
+        lock_delay.lift();
+        lock_delay.stepped_down(9);
+        assert_eq!(locks_after(&mut lock_delay, 100), Some(3));
+    }
+
+    #[test]
+    fn durations_by_level() {
+        let durations = LockDelay::parse_durations("0:500,200:400,500:300")
+            .expect("Should be valid durations");
+        let lock_delay = LockDelay::new(LockPolicy::StepReset, durations);
+
+        assert_eq!(lock_delay.duration(0), Duration::from_millis(500));
+        assert_eq!(lock_delay.duration(250), Duration::from_millis(400));
+        assert_eq!(lock_delay.duration(999), Duration::from_millis(300));
+
+        assert_eq!(
+            LockDelay::parse_durations("250"),
+            Some(vec![(0, Duration::from_millis(250))])
+        );
+        assert_eq!(LockDelay::parse_durations("200:400,0:500"), None);
+    }
+
+    #[rstest]
+    #[case("move", Some(LockPolicy::MoveReset { limit: 15 }))]
+    #[case("move:10", Some(LockPolicy::MoveReset { limit: 10 }))]
+    #[case("step", Some(LockPolicy::StepReset))]
+    #[case("infinite", Some(LockPolicy::Infinite))]
+    #[case("immediate", Some(LockPolicy::Immediate))]
+    #[case("move:many", None)]
+    fn from_name(#[case] name: &str, #[case] expected: Option<LockPolicy>) {
+        assert_eq!(LockPolicy::from_name(name), expected);
+    }
+}
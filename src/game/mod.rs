@@ -1,12 +1,27 @@
+mod auto_shift;
+mod handling;
+mod lock_delay;
+mod state;
+#[cfg(test)]
+pub(crate) mod testing;
 mod timing;
 
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
-use crate::engine::{kick_tables::SrsPlus, Engine, MoveKind, RotateKind};
+use crate::engine::{
+    kick_tables::SrsPlus, Engine, Gravity, Matrix, MoveKind, RotateKind,
+};
 
-pub use self::timing::{DeltaTime, Timer};
+use self::auto_shift::AutoShift;
 
-#[derive(Debug)]
+pub use self::{
+    handling::{Handling, ParseHandlingError, SocdPolicy, SoftDropFactor},
+    lock_delay::{LockDelay, LockPolicy},
+    state::{Delays, State},
+    timing::{DeltaTime, TickRate, Timer},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct InputAction {
     pub input: Input,
     pub action: KeyAction,
@@ -18,212 +33,1022 @@ impl InputAction {
     }
 }
 
-#[derive(Debug)]
+/// An input handled right before the given tick is simulated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedInput {
+    pub tick: u64,
+    pub action: InputAction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum KeyAction {
     Press,
     Release,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Input {
     Rotate(RotateKind),
     Move(MoveKind),
     HardDrop,
     SoftDrop,
     Hold,
+    CycleHold,
+    Pause,
 }
 
-#[derive(Default)]
+#[derive(Clone)]
 pub struct Game {
     // TODO: maybe re-expose necessary engine methods
     //       instead of having the engine public
     pub engine: Engine,
 
-    tick_timer: Timer,
-    fast_timer: Timer,
-    lock_timer: Timer,
-    move_repeat_timer: Timer,
-
-    repeat_move: bool,
+    tick_rate: TickRate,
+    /// Ticks stepped so far, paused ones included.
+    tick: u64,
+    /// Inputs waiting for their tick, in order.
+    inputs: VecDeque<TimedInput>,
+    /// Inputs handled so far, at the tick they were handled on.
+    handled_inputs: Vec<TimedInput>,
+    state: State,
+    /// Runs the delay of the countdown, spawning and clearing states.
+    state_timer: Timer,
+    delays: Delays,
 
-    prev_move: Option<MoveKind>,
-    current_move: Option<MoveKind>,
+    /// Rows gravity has pulled the cursor that are not fallen yet.
+    fall: f32,
+    lock_delay: LockDelay,
+    handling: Handling,
+    auto_shift: AutoShift,
 
-    lock_reset: bool,
-    lock_moves: i32,
-
-    hard_drop: bool,
     soft_drop: bool,
 
-    hold_available: bool,
+    /// Rotation and hold keys currently held down, for IRS and IHS. Both
+    /// rotate keys can be held, the last one pressed wins.
+    held_rotations: Vec<RotateKind>,
+    hold_held: bool,
 }
 
 impl Game {
-    pub const LOCK_MOVES: i32 = 15;
-    pub const SPEED_MULT: u32 = 20;
-    pub const LOCK_TIME: Duration = Duration::from_millis(500);
-    pub const FIRST_MOVE_DELAY: Duration = Duration::from_millis(300);
-    pub const MOVE_REPEAT_DELAY: Duration = Duration::from_millis(35);
+    pub fn new(engine: Engine) -> Self {
+        //NOTE: a practice setup may come with its piece in play
+        let state = match engine.cursor_info() {
+            Some(_) => State::Falling,
+            None => State::Countdown,
+        };
 
-    pub(super) fn new(engine: Engine) -> Self {
         Self {
             engine,
-            lock_timer: Timer::new(Self::LOCK_TIME),
-            lock_moves: Self::LOCK_MOVES,
-            hold_available: true,
-            ..Default::default()
+            tick_rate: TickRate::default(),
+            tick: 0,
+            inputs: VecDeque::new(),
+            handled_inputs: Vec::new(),
+            state,
+            state_timer: Timer::default(),
+            delays: Delays::default(),
+            fall: 0.0,
+            lock_delay: LockDelay::default(),
+            handling: Handling::default(),
+            auto_shift: AutoShift::default(),
+            soft_drop: false,
+            held_rotations: Vec::new(),
+            hold_held: false,
         }
+        .configured()
     }
 
-    //TODO: Have this return if the move was successful
-    fn move_cursor(&mut self, kind: MoveKind) {
-        let move_res = self.engine.move_cursor(kind);
-        if move_res.is_err() {
-            return;
+    pub fn with_tick_rate(self, tick_rate: TickRate) -> Self {
+        Self { tick_rate, ..self }.configured()
+    }
+
+    pub fn with_delays(self, delays: Delays) -> Self {
+        Self { delays, ..self }.configured()
+    }
+
+    /// Converts the settings to ticks, once they or the tick rate change.
+    fn configured(mut self) -> Self {
+        self.auto_shift = AutoShift::new(&self.handling, self.tick_rate);
+        if self.state == State::Countdown {
+            self.state_timer = self.delay_timer(self.delays.countdown);
         }
-        self.lock_reset = true;
+        self
+    }
 
-        if self.current_move == Some(kind) {
-            self.repeat_move = true;
-        } else {
-            self.repeat_move = false;
-            self.prev_move = self.current_move;
-            self.current_move = Some(kind);
+    fn delay_timer(&self, delay: Duration) -> Timer {
+        Timer::new(self.tick_rate.ticks(delay))
+    }
+
+    pub fn tick_rate(&self) -> TickRate {
+        self.tick_rate
+    }
+
+    /// The next tick to be simulated.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Queues an input for its tick. Inputs for ticks already stepped are
+    /// handled on the next one.
+    pub fn queue_input(&mut self, input: TimedInput) {
+        let index = self
+            .inputs
+            .partition_point(|queued| queued.tick <= input.tick);
+        self.inputs.insert(index, input);
+    }
+
+    /// Every input handled so far, enough to play the game again.
+    pub fn handled_inputs(&self) -> &[TimedInput] {
+        &self.handled_inputs
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// How far along, from 0 to 1, the delay of the current state is.
+    pub fn state_progress(&self) -> f32 {
+        self.state_timer.progress()
+    }
+
+    /// Cleared lines and how far along, from 0 to 1, their clear is.
+    pub fn line_clear_progress(&self) -> Option<(&[usize], f32)> {
+        match &self.state {
+            State::Clearing(lines) => {
+                Some((lines.as_slice(), self.state_timer.progress()))
+            }
+            _ => None,
         }
     }
 
-    fn move_stop(&mut self, kind: MoveKind) {
-        if self.current_move == Some(kind) {
-            self.current_move = self.prev_move.take();
-        } else {
-            self.prev_move = None;
+    /// Pauses the game, or resumes it if it is paused.
+    pub fn toggle_pause(&mut self) {
+        let next = match &self.state {
+            State::Paused(paused) => (**paused).clone(),
+            State::GameOver => return,
+            state => State::Paused(Box::new(state.clone())),
+        };
+        self.transition(next);
+    }
+
+    pub fn with_lock_delay(self, lock_delay: LockDelay) -> Self {
+        Self { lock_delay, ..self }
+    }
+
+    pub fn with_handling(self, handling: Handling) -> Self {
+        Self { handling, ..self }.configured()
+    }
+
+    pub fn handling(&self) -> &Handling {
+        &self.handling
+    }
+
+    /// Moves the cursor up to `cells` times, stopping at the first wall.
+    /// At 20G it lands after every cell, so it drops into the holes it
+    /// passes over instead of sliding across them.
+    fn move_cursor(&mut self, kind: MoveKind, cells: usize) {
+        for _ in 0..cells {
+            if self.engine.move_cursor(kind).is_err() {
+                break;
+            }
+            self.lock_delay.moved();
+            if self.fall_gravity().is_instant() {
+                self.fall();
+            }
         }
     }
 
     fn cursor_kicks(&mut self, rotate_kind: RotateKind) {
-        let (_, _, kind, rotation) = self.engine.cursor_info().unwrap();
+        let Some((_, _, kind, rotation)) = self.engine.cursor_info() else {
+            return;
+        };
         let kick_table = SrsPlus::new(kind, rotation, rotate_kind);
         for kick in kick_table.get_kicks() {
             if self.engine.rotate_cursor(rotate_kind, Some(kick)).is_ok() {
-                self.lock_reset = true;
+                self.rotated();
                 break;
             }
         }
     }
 
+    fn rotated(&mut self) {
+        self.lock_delay.moved();
+        if self.handling.rotate_cut {
+            self.auto_shift.cancel();
+        }
+        self.auto_shift.pause();
+    }
+
     // TODO: Have this return if the rotation was successful
     fn rotate_cursor(&mut self, rotate_kind: RotateKind) {
         match self.engine.rotate_cursor(rotate_kind, None) {
-            Ok(_) => self.lock_reset = true,
+            Ok(_) => self.rotated(),
             //BUG: T Pieces are not kicking properly (T-Spins)
             Err(_) => self.cursor_kicks(rotate_kind),
         }
     }
 
-    //TODO: return Result<(), ()>
+    /// Swaps the cursor with the hold. The piece swapped in starts over
+    /// like a spawned one, ending the game if it spawns on the stack.
     fn hold_cursor(&mut self) {
-        if !self.hold_available {
+        //NOTE: a refused hold is part of the rules, nothing to report
+        if self.engine.hold_cursor().is_err() {
             return;
         }
+        self.fall = 0.0;
+        self.lock_delay.reset();
+        //NOTE: on spawn, the entry delay checks for a block out itself
+        if self.state.piece_in_play() && self.engine.cursor_is_blocked() {
+            self.transition(State::GameOver);
+        }
+    }
 
-        self.engine.hold_cursor();
-        self.hold_available = false;
+    fn cycle_hold(&mut self) {
+        let _ = self.engine.cycle_hold();
     }
 
     pub fn handle_input(&mut self, InputAction { input, action }: InputAction) {
         use Input::*;
         use KeyAction::*;
+
+        //NOTE: releases always go through, so held keys are right on resume.
+        //      During the countdown, rotation and hold are only held for the
+        //      initial actions of the first piece.
+        let accepted = match self.state {
+            State::Countdown => matches!(input, Rotate(_) | Hold),
+            State::GameOver => false,
+            State::Paused(_) => input == Pause,
+            _ => true,
+        };
+        if action == Press && !accepted {
+            return;
+        }
+
         match (input, action) {
-            (Rotate(kind), Press) => self.rotate_cursor(kind),
-            (Rotate(_), Release) => {}
+            (Rotate(kind), Press) => {
+                self.held_rotations.retain(|held| *held != kind);
+                self.held_rotations.push(kind);
+                self.rotate_cursor(kind)
+            }
+            (Rotate(kind), Release) => {
+                self.held_rotations.retain(|held| *held != kind)
+            }
 
-            (Move(kind), Press) => self.move_cursor(kind),
-            (Move(kind), Release) => self.move_stop(kind),
+            (Move(kind), Press) => {
+                self.auto_shift.press(kind);
+                if self.auto_shift.direction() == Some(kind) {
+                    self.move_cursor(kind, 1);
+                }
+            }
+            (Move(kind), Release) => self.auto_shift.release(kind),
 
-            (HardDrop, Press) => self.hard_drop = true,
+            //NOTE: not buffered, it would lock the next piece on spawn
+            (HardDrop, Press) => {
+                if self.state.piece_in_play() {
+                    if self.handling.drop_cut {
+                        self.auto_shift.cancel();
+                    }
+                    self.engine.hard_drop();
+                    self.lock_cursor();
+                }
+            }
             (HardDrop, Release) => {} // NOTE: Does nothing
 
             (SoftDrop, Press) => self.soft_drop = true,
             (SoftDrop, Release) => self.soft_drop = false,
 
-            (Hold, Press) => self.hold_cursor(),
-            (Hold, Release) => {}
+            (Hold, Press) => {
+                self.hold_held = true;
+                self.hold_cursor()
+            }
+            (Hold, Release) => self.hold_held = false,
+
+            (CycleHold, Press) => self.cycle_hold(),
+            (CycleHold, Release) => {}
+
+            (Pause, Press) => self.toggle_pause(),
+            (Pause, Release) => {}
         }
     }
 
-    fn update_timers(&mut self, tick_time: Duration, delta_time: DeltaTime) {
-        self.tick_timer.set_target(tick_time);
-        self.fast_timer.set_target(tick_time / Self::SPEED_MULT);
+    /// Spawns the next piece, holding then rotating it if IHS and IRS
+    /// apply. Rotations use the same kicks as regular ones.
+    fn spawn_cursor(&mut self) {
+        self.fall = 0.0;
+        self.lock_delay.reset();
+        match self.handling.preserve_charge {
+            true => self.auto_shift.pause(),
+            false => self.auto_shift.cancel(),
+        }
+        self.engine.add_cursor();
+        if !self.handling.initial_actions {
+            return;
+        }
 
-        self.tick_timer.update(delta_time);
-        self.fast_timer.update(delta_time);
-        self.lock_timer.update(delta_time);
-        self.move_repeat_timer.update(delta_time);
+        if self.hold_held {
+            self.hold_cursor();
+        }
+        if let Some(&kind) = self.held_rotations.last() {
+            self.rotate_cursor(kind);
+        }
     }
 
-    //TODO: Take a second look at this function to
-    //      see if it can be simplified / split up
-    //
-    //TODO: Return a struct with game state info
-    //      to be used for rendering / animation
-    //      networking / etc.
-    pub fn update(&mut self, delta_time: DeltaTime) {
-        if self.engine.cursor_info().is_none() {
-            self.engine.add_cursor();
+    /// The gravity pulling the cursor, boosted while soft dropping.
+    fn fall_gravity(&self) -> Gravity {
+        let gravity = self.engine.gravity();
+        match (self.soft_drop, self.handling.sdf) {
+            (false, _) => gravity,
+            (true, SoftDropFactor::Factor(factor)) => gravity.boosted(factor),
+            (true, SoftDropFactor::Instant) => Gravity::MAX,
         }
+    }
 
-        if self.repeat_move {
-            self.move_repeat_timer.set_target(Self::MOVE_REPEAT_DELAY);
-        } else {
-            self.move_repeat_timer.set_target(Self::FIRST_MOVE_DELAY);
+    /// Moves the cursor down by the whole rows gravity pulled it this
+    /// frame. At 20G it lands right away, so it never shows floating after
+    /// a spawn or a move.
+    fn fall(&mut self) {
+        let gravity = self.fall_gravity();
+        self.fall += match gravity.is_instant() {
+            true => Matrix::HEIGHT as f32,
+            false => gravity.rows(self.tick_rate.tick_length()),
+        };
+        while self.fall >= 1.0 && !self.engine.cursor_has_hit_bottom() {
+            self.fall -= 1.0;
+            self.engine.tick_down();
+            self.lock_delay.stepped_down(self.engine.cursor_row());
         }
 
-        self.update_timers(self.engine.drop_time(), delta_time);
+        if self.engine.cursor_has_hit_bottom() {
+            self.fall = 0.0;
+        }
+    }
 
-        if let Some(move_kind) = self.current_move {
-            if self.move_repeat_timer.just_finished() {
-                //TODO: have this result wrapped and returned
-                self.move_cursor(move_kind);
-            }
-        } else {
-            self.repeat_move = false;
-            self.move_repeat_timer.reset();
+    /// Locks the cursor, starting the line clear or the entry delay.
+    fn lock_cursor(&mut self) {
+        //NOTE: lock out, the piece cannot lock above the matrix
+        if self.engine.cursor_is_out() {
+            return self.transition(State::GameOver);
+        }
+        if self.state == State::Falling {
+            self.transition(State::Locking);
         }
+        self.engine.place_cursor();
 
-        if self.hard_drop {
-            self.engine.hard_drop()
+        let lines = self.engine.full_lines();
+        match lines.is_empty() {
+            true => self.transition(State::Spawning),
+            false => self.transition(State::Clearing(lines)),
         }
+    }
 
-        let mut check_lines = false;
-        if self.engine.cursor_has_hit_bottom() {
-            if self.lock_reset && self.lock_moves > 0 {
-                self.lock_timer.reset();
-                self.lock_reset = false;
-                self.lock_moves -= 1;
+    /// Moves to the next state, running the exit event of the current one
+    /// and the entry event of the next. Pausing and resuming skip them, so
+    /// the game resumes exactly where it was.
+    fn transition(&mut self, next: State) {
+        debug_assert!(
+            self.state.can_become(&next),
+            "Invalid transition from {:?} to {:?}",
+            self.state,
+            next
+        );
+        let previous = std::mem::replace(&mut self.state, next);
+        if matches!(previous, State::Paused(_))
+            || matches!(self.state, State::Paused(_))
+        {
+            return;
+        }
+
+        self.on_exit(&previous);
+        self.on_enter(&previous);
+    }
+
+    fn on_exit(&mut self, state: &State) {
+        if let State::Clearing(lines) = state {
+            self.engine.clear_lines(lines);
+        }
+    }
+
+    fn on_enter(&mut self, previous: &State) {
+        match &self.state {
+            State::Countdown => {
+                self.state_timer = self.delay_timer(self.delays.countdown)
+            }
+            State::Spawning => {
+                //NOTE: the countdown stands in for the first entry delay
+                let delay = match previous {
+                    State::Countdown => Duration::ZERO,
+                    State::Clearing(_) => self.delays.line_are,
+                    _ => self.delays.are,
+                };
+                self.state_timer = self.delay_timer(delay);
             }
+            State::Clearing(_) => {
+                self.state_timer = self.delay_timer(self.delays.line_clear)
+            }
+            //NOTE: block out, show the stack for good
+            State::GameOver => self.engine.reveal_stack(Duration::MAX),
+            State::Falling | State::Locking | State::Paused(_) => {}
+        }
+    }
 
-            //TODO: rethink how the hard drop is handled
-            if self.hard_drop || self.lock_timer.just_finished() {
-                self.hard_drop = false;
-                check_lines = true;
-                self.engine.place_cursor();
+    /// Ends the delay of the countdown, spawning or clearing state.
+    fn finish_delay(&mut self) {
+        let next = match &self.state {
+            State::Countdown | State::Clearing(_) => State::Spawning,
+            State::Spawning => {
+                self.spawn_cursor();
+                match self.engine.cursor_is_blocked() {
+                    true => State::GameOver,
+                    false => State::Falling,
+                }
+            }
+            state => unreachable!("{state:?} has no delay"),
+        };
+        self.transition(next);
+    }
+
+    /// Runs the piece in play: auto shift, gravity and the lock delay.
+    fn update_piece(&mut self) {
+        let shifts = self.auto_shift.take_shifts();
+        if let Some(kind) = self.auto_shift.direction() {
+            self.move_cursor(kind, shifts);
+        }
+
+        let grounded = self.engine.cursor_has_hit_bottom();
+        match (&self.state, grounded) {
+            (State::Falling, true) => self.transition(State::Locking),
+            (State::Locking, false) => self.transition(State::Falling),
+            _ => {}
+        }
+
+        if grounded {
+            let level = self.engine.level();
+            if self.lock_delay.tick(level, self.tick_rate) {
+                self.lock_cursor();
             }
         } else {
-            self.lock_timer.reset();
+            self.lock_delay.lift();
+            self.fall();
+        }
+    }
 
-            let tick = self.tick_timer.just_finished();
-            let fast_tick = self.fast_timer.just_finished();
-            if (self.soft_drop && fast_tick) || tick {
-                self.engine.tick_down();
-                self.lock_moves = Self::LOCK_MOVES;
+    //TODO: Return a struct with game state info
+    //      to be used for rendering / animation
+    //      networking / etc.
+    /// Advances the game by one tick, handling the inputs queued for it
+    /// first. Given the same inputs at the same ticks, the game always
+    /// plays out the same.
+    pub fn step(&mut self) {
+        while let Some(input) = self
+            .inputs
+            .front()
+            .filter(|input| input.tick <= self.tick)
+            .copied()
+        {
+            self.inputs.pop_front();
+            self.handle_input(input.action);
+            //NOTE: late inputs are recorded at the tick they took effect on
+            self.handled_inputs.push(TimedInput {
+                tick: self.tick,
+                ..input
+            });
+        }
+        self.tick += 1;
+
+        if matches!(self.state, State::Paused(_) | State::GameOver) {
+            return;
+        }
+        self.engine.advance_clock(self.tick_rate.tick_length());
+
+        //NOTE: DAS charges even without a piece in play, so a key held
+        //      through the entry delay shifts the next piece right away
+        let charging = match self.state {
+            State::Clearing(_) => self.handling.line_clear_charge,
+            _ => true,
+        };
+        if charging {
+            self.auto_shift.tick();
+        }
+
+        //NOTE: states without delay are over right away, but the tick is
+        //      only spent once, on a delay or on the piece
+        let mut ticks = 1;
+        let mut piece_updated = false;
+        loop {
+            match self.state {
+                State::Falling | State::Locking if !piece_updated => {
+                    piece_updated = true;
+                    self.update_piece();
+                    ticks = 0;
+                }
+                State::Countdown | State::Spawning | State::Clearing(_) => {
+                    self.state_timer.advance(std::mem::take(&mut ticks));
+                    if !self.state_timer.just_finished() {
+                        return;
+                    }
+                    self.finish_delay();
+                }
+                _ => return,
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::engine::{Rotation, SpawnRule};
+
+    use self::testing::{play_pressing, timed};
+
+    const CW: Input = Input::Rotate(RotateKind::Clockwise);
+    const CCW: Input = Input::Rotate(RotateKind::CounterClockwise);
+
+    /// Steps through the ticks of `duration`.
+    fn run(game: &mut Game, duration: Duration) {
+        for _ in 0..game.tick_rate.ticks(duration) {
+            game.step();
+        }
+    }
 
-        if check_lines {
-            //TODO: change this funtion to return the cleared
-            //      lines indices
-            self.engine.line_clear(|_| (/*canvas animation*/));
-            self.hold_available = true;
+    fn spawned_game(
+        initial_actions: bool,
+        keys: &[(Input, KeyAction)],
+    ) -> Game {
+        let mut game = Game::new(Engine::new()).with_handling(Handling {
+            initial_actions,
+            ..Default::default()
+        });
+        for &(input, action) in keys {
+            game.handle_input(InputAction::new(input, action));
         }
+        game.step();
+        game
+    }
+
+    #[test]
+    fn fractional_gravity_builds_up() {
+        let mut game = Game::new(Engine::new());
+        game.step();
+        let (spawned, ..) = game.engine.cursor_info().unwrap();
+
+        // level 1 falls a row per second
+        run(&mut game, Duration::from_millis(600));
+        assert_eq!(game.engine.cursor_info().unwrap().0, spawned);
+
+        run(&mut game, Duration::from_millis(600));
+        let (fallen, ..) = game.engine.cursor_info().unwrap();
+        assert_eq!(fallen[0].y + 1, spawned[0].y);
+    }
+
+    #[test]
+    fn twenty_g_lands_on_spawn() {
+        let mut game = Game::new(Engine::new().with_level(20));
+        assert!(game.engine.gravity().is_instant());
+
+        game.step();
+        assert!(game.engine.cursor_has_hit_bottom());
+    }
+
+    #[test]
+    fn same_seed_and_inputs_play_the_same() {
+        let play = || {
+            let mut game = Game::new(Engine::new().with_seed(7));
+            let inputs = [Input::Move(MoveKind::Left), Input::HardDrop];
+            play_pressing(&mut game, &inputs, 40, 0, 600);
+            game.engine.history_to_fumen()
+        };
+        assert_eq!(play(), play());
+    }
+
+    /// Column of the leftmost cursor cell.
+    fn cursor_column(game: &Game) -> usize {
+        let (cells, ..) = game.engine.cursor_info().unwrap();
+        cells.iter().map(|coord| coord.x).min().unwrap()
+    }
+
+    #[test]
+    fn queued_inputs_wait_for_their_tick() {
+        let mut game = spawned_game(false, &[]);
+        let spawned = cursor_column(&game);
+        let tick = game.tick();
+
+        //NOTE: queued out of order, taps within a single tick
+        let left = Input::Move(MoveKind::Left);
+        game.queue_input(timed(tick + 2, left, KeyAction::Press));
+        game.queue_input(timed(tick + 2, left, KeyAction::Release));
+        game.queue_input(timed(tick + 1, left, KeyAction::Press));
+        game.queue_input(timed(tick + 1, left, KeyAction::Release));
+
+        game.step();
+        assert_eq!(cursor_column(&game), spawned);
+        game.step();
+        assert_eq!(cursor_column(&game), spawned - 1);
+        game.step();
+        assert_eq!(cursor_column(&game), spawned - 2);
+
+        //NOTE: late inputs are not lost
+        game.queue_input(timed(0, left, KeyAction::Press));
+        game.step();
+        assert_eq!(cursor_column(&game), spawned - 3);
+    }
+
+    #[test]
+    fn queued_inputs_resume_a_paused_game() {
+        let mut game = spawned_game(false, &[]);
+        let tick = game.tick();
+        game.queue_input(timed(tick, Input::Pause, KeyAction::Press));
+        game.queue_input(timed(tick + 5, Input::Pause, KeyAction::Press));
+
+        for _ in 0..5 {
+            game.step();
+            assert!(matches!(game.state, State::Paused(_)));
+        }
+        game.step();
+        assert_eq!(game.state, State::Falling);
+    }
+
+    #[test]
+    fn entry_delay() {
+        let mut game = Game::new(Engine::new()).with_delays(Delays {
+            are: Duration::from_millis(100),
+            ..Default::default()
+        });
+        // the first piece comes without delay
+        game.step();
+        assert_eq!(game.state, State::Falling);
+
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        game.step();
+        assert_eq!(game.state, State::Spawning);
+        assert!(game.engine.cursor_info().is_none());
+        assert!(game.engine.move_cursor(MoveKind::Left).is_err());
+
+        run(&mut game, Duration::from_millis(60));
+        assert!(game.engine.cursor_info().is_none());
+        run(&mut game, Duration::from_millis(60));
+        assert!(game.engine.cursor_info().is_some());
+    }
+
+    #[test]
+    fn line_clear_delay() {
+        let engine = "
+            ......iiii
+            GGGGGG....
+        "
+        .parse()
+        .expect("Should be a valid board");
+        let mut game = Game::new(engine).with_delays(Delays {
+            line_clear: Duration::from_millis(100),
+            ..Default::default()
+        });
+
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        assert_eq!(game.state, State::Clearing(vec![0]));
+        assert_eq!(game.line_clear_progress(), Some(([0].as_slice(), 0.0)));
+
+        run(&mut game, Duration::from_millis(50));
+        assert_eq!(game.engine.full_lines(), [0]);
+
+        run(&mut game, Duration::from_millis(50));
+        assert!(game.engine.full_lines().is_empty());
+        assert_eq!(game.state, State::Falling);
+    }
+
+    #[test]
+    fn huge_levels_score_the_most_they_can() {
+        let engine = "
+            ......iiii
+            GGGGGG....
+        "
+        .parse::<Engine>()
+        .expect("Should be a valid board")
+        .with_level(u32::MAX);
+        let mut game = Game::new(engine);
+
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        run(&mut game, Duration::from_secs(1));
+        assert_eq!(game.engine.lines(), 1);
+        assert_eq!(game.engine.score(), u32::MAX);
+    }
+
+    #[test]
+    fn locking_above_the_matrix_ends_the_game() {
+        let stack = "GGGGGGGGG.\n".repeat(Matrix::HEIGHT - 1);
+        let engine = stack
+            .parse::<Engine>()
+            .expect("Should be a valid board")
+            .with_seed(1);
+        let mut game = Game::new(engine);
+        game.step();
+        assert_eq!(game.state, State::Locking);
+
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        assert_eq!(game.state, State::GameOver);
+    }
+
+    #[test]
+    fn zero_arr_reaches_the_wall() {
+        let mut game = spawned_game(false, &[]).with_handling(Handling {
+            das: Duration::from_millis(100),
+            arr: Duration::ZERO,
+            ..Default::default()
+        });
+        let column = |game: &Game| {
+            let (cells, ..) = game.engine.cursor_info().unwrap();
+            cells.iter().map(|coord| coord.x).min().unwrap()
+        };
+        let spawned = column(&game);
+
+        game.handle_input(InputAction::new(
+            Input::Move(MoveKind::Left),
+            KeyAction::Press,
+        ));
+        assert_eq!(column(&game), spawned - 1);
+
+        run(&mut game, Duration::from_millis(100));
+        assert_eq!(column(&game), 0);
+    }
+
+    #[test]
+    fn twenty_g_shifts_drop_into_gaps() {
+        let engine = "
+            i.........
+            i.........
+            i.........
+            i.........
+            GG.GGGGGGG
+        "
+        .parse::<Engine>()
+        .expect("Should be a valid board")
+        .with_level(20);
+        let mut game = Game::new(engine).with_handling(Handling {
+            das: Duration::from_millis(100),
+            arr: Duration::ZERO,
+            ..Default::default()
+        });
+        game.handle_input(InputAction::new(
+            Input::Move(MoveKind::Right),
+            KeyAction::Press,
+        ));
+        run(&mut game, Duration::from_millis(100));
+
+        let (cells, ..) = game.engine.cursor_info().unwrap();
+        assert!(cells.iter().all(|coord| coord.x == 2));
+        assert!(cells.iter().any(|coord| coord.y == 0));
+    }
+
+    /// A game with a piece in play and left held until DAS charged.
+    fn charged_game(handling: Handling) -> Game {
+        let mut game = spawned_game(false, &[]).with_handling(Handling {
+            das: Duration::from_millis(100),
+            ..handling
+        });
+        game.handle_input(InputAction::new(
+            Input::Move(MoveKind::Left),
+            KeyAction::Press,
+        ));
+        run(&mut game, Duration::from_millis(100));
+        assert!(game.auto_shift.is_charged());
+        game
+    }
+
+    #[rstest]
+    #[case(false, true)]
+    #[case(true, false)]
+    fn das_cut_on_rotation(#[case] rotate_cut: bool, #[case] charged: bool) {
+        let mut game = charged_game(Handling {
+            arr: Duration::from_secs(1),
+            rotate_cut,
+            ..Default::default()
+        });
+        game.handle_input(InputAction::new(
+            Input::Rotate(RotateKind::Clockwise),
+            KeyAction::Press,
+        ));
+        assert_eq!(game.auto_shift.is_charged(), charged);
+    }
+
+    #[rstest]
+    #[case(true, false, true)]
+    #[case(false, false, false)]
+    #[case(true, true, false)]
+    fn das_across_pieces(
+        #[case] preserve_charge: bool,
+        #[case] drop_cut: bool,
+        #[case] charged: bool,
+    ) {
+        let mut game = charged_game(Handling {
+            arr: Duration::ZERO,
+            preserve_charge,
+            drop_cut,
+            ..Default::default()
+        });
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        run(&mut game, Duration::from_millis(16));
+        assert_eq!(game.state, State::Falling);
+        assert_eq!(game.auto_shift.is_charged(), charged);
+
+        //NOTE: a charged DAS with instant ARR takes the new piece to the wall
+        let (cells, ..) = game.engine.cursor_info().unwrap();
+        let at_wall = cells.iter().any(|coord| coord.x == 0);
+        assert_eq!(at_wall, charged);
+    }
+
+    #[rstest]
+    #[case(true, true)]
+    #[case(false, false)]
+    fn das_during_line_clear(
+        #[case] line_clear_charge: bool,
+        #[case] charged: bool,
+    ) {
+        let engine = "
+            ......iiii
+            GGGGGG....
+        "
+        .parse()
+        .expect("Should be a valid board");
+        let mut game = Game::new(engine)
+            .with_delays(Delays {
+                line_clear: Duration::from_millis(200),
+                ..Default::default()
+            })
+            .with_handling(Handling {
+                das: Duration::from_millis(100),
+                line_clear_charge,
+                ..Default::default()
+            });
+
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        game.handle_input(InputAction::new(
+            Input::Move(MoveKind::Left),
+            KeyAction::Press,
+        ));
+        run(&mut game, Duration::from_millis(100));
+        assert_eq!(game.state, State::Clearing(vec![0]));
+        assert_eq!(game.auto_shift.is_charged(), charged);
+    }
+
+    #[rstest]
+    #[case(SoftDropFactor::Factor(20.0), false)]
+    #[case(SoftDropFactor::Instant, true)]
+    fn soft_drop_factor(#[case] sdf: SoftDropFactor, #[case] lands: bool) {
+        let mut game = spawned_game(false, &[]).with_handling(Handling {
+            sdf,
+            ..Default::default()
+        });
+        game.handle_input(InputAction::new(Input::SoftDrop, KeyAction::Press));
+        run(&mut game, Duration::from_millis(16));
+
+        assert_eq!(game.engine.cursor_has_hit_bottom(), lands);
+        assert_eq!(game.state, State::Falling);
+    }
+
+    #[test]
+    fn countdown_blocks_input() {
+        let mut game = Game::new(Engine::new()).with_delays(Delays {
+            countdown: Duration::from_millis(100),
+            ..Default::default()
+        });
+        game.handle_input(InputAction::new(Input::Pause, KeyAction::Press));
+        run(&mut game, Duration::from_millis(60));
+        assert_eq!(game.state, State::Countdown);
+        assert!(game.engine.cursor_info().is_none());
+
+        run(&mut game, Duration::from_millis(60));
+        assert_eq!(game.state, State::Falling);
+    }
+
+    #[test]
+    fn pause_freezes_the_game() {
+        let mut game = spawned_game(false, &[]);
+        let (spawned, ..) = game.engine.cursor_info().unwrap();
+
+        game.handle_input(InputAction::new(Input::Pause, KeyAction::Press));
+        assert_eq!(game.state, State::Paused(Box::new(State::Falling)));
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        run(&mut game, Duration::from_secs(5));
+        assert_eq!(game.engine.cursor_info().unwrap().0, spawned);
+
+        game.handle_input(InputAction::new(Input::Pause, KeyAction::Press));
+        assert_eq!(game.state, State::Falling);
+        game.handle_input(InputAction::new(Input::HardDrop, KeyAction::Press));
+        assert_eq!(game.state, State::Spawning);
+    }
+
+    #[rstest]
+    #[case(true, &[], Rotation::N)]
+    #[case(true, &[(CW, KeyAction::Press)], Rotation::E)]
+    #[case(true, &[(CCW, KeyAction::Press)], Rotation::W)]
+    #[case(
+        true,
+        &[(CW, KeyAction::Press), (CW, KeyAction::Release)],
+        Rotation::N
+    )]
+    #[case(
+        true,
+        &[(CW, KeyAction::Press), (CCW, KeyAction::Press)],
+        Rotation::W
+    )]
+    #[case(
+        true,
+        &[
+            (CW, KeyAction::Press),
+            (CCW, KeyAction::Press),
+            (CCW, KeyAction::Release),
+        ],
+        Rotation::E
+    )]
+    #[case(
+        true,
+        &[
+            (CW, KeyAction::Press),
+            (CCW, KeyAction::Press),
+            (CW, KeyAction::Release),
+        ],
+        Rotation::W
+    )]
+    #[case(false, &[(CW, KeyAction::Press)], Rotation::N)]
+    fn initial_rotation(
+        #[case] initial_actions: bool,
+        #[case] keys: &[(Input, KeyAction)],
+        #[case] expected: Rotation,
+    ) {
+        let game = spawned_game(initial_actions, keys);
+        let (_, _, _, rotation) = game.engine.cursor_info().unwrap();
+        assert_eq!(rotation, expected);
+    }
+
+    #[rstest]
+    #[case(true, &[(Input::Hold, KeyAction::Press)], 1)]
+    #[case(
+        true,
+        &[(Input::Hold, KeyAction::Press), (Input::Hold, KeyAction::Release)],
+        0
+    )]
+    #[case(false, &[(Input::Hold, KeyAction::Press)], 0)]
+    fn initial_hold(
+        #[case] initial_actions: bool,
+        #[case] keys: &[(Input, KeyAction)],
+        #[case] held: usize,
+    ) {
+        let game = spawned_game(initial_actions, keys);
+        assert_eq!(game.engine.held_cursor_info().len(), held);
+        assert!(game.engine.cursor_info().is_some());
+    }
+
+    #[test]
+    fn hold_starts_the_fall_over() {
+        let mut game = spawned_game(false, &[]);
+        // level 1 falls a row per second
+        run(&mut game, Duration::from_millis(600));
+        game.handle_input(InputAction::new(Input::Hold, KeyAction::Press));
+        let (swapped, ..) = game.engine.cursor_info().unwrap();
+
+        run(&mut game, Duration::from_millis(600));
+        assert_eq!(game.engine.cursor_info().unwrap().0, swapped);
+    }
+
+    #[test]
+    fn holding_onto_the_stack_ends_the_game() {
+        let stack = "...GGGG...\n".repeat(Matrix::HEIGHT - 4)
+            + &"i..GGGG...\n".repeat(4);
+        let engine = stack
+            .parse::<Engine>()
+            .expect("Should be a valid board")
+            .with_spawn_rule(SpawnRule::InField);
+        let mut game = Game::new(engine);
+        assert_eq!(game.state, State::Falling);
+
+        game.handle_input(InputAction::new(Input::Hold, KeyAction::Press));
+        assert!(game.engine.cursor_is_blocked());
+        assert_eq!(game.state, State::GameOver);
+    }
+
+    #[test]
+    fn next_piece_is_the_first_previewed() {
+        let mut game = Game::new(Engine::new().with_seed(7));
+        game.step();
+        let preview = game.engine.next_pieces();
+        assert_eq!(preview.len(), Engine::PREVIEW_COUNT);
+
+        game.engine.hard_drop();
+        game.engine.place_cursor();
+        game.engine.add_cursor();
+        let (_, color, ..) = game.engine.cursor_info().unwrap();
+        assert_eq!(color, preview[0].1);
+        assert_eq!(game.engine.next_pieces()[..4], preview[1..]);
+    }
+
+    #[test]
+    fn ghost_is_where_the_piece_lands() {
+        let mut game = Game::new(Engine::new().with_seed(7));
+        game.step();
+        let ghost = game.engine.ghost_cells().unwrap();
+
+        game.engine.hard_drop();
+        assert_eq!(game.engine.cursor_info().unwrap().0, ghost);
     }
 }
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+/// What the game is doing. A piece is only in play while
+/// [`State::Falling`] or [`State::Locking`], other states wait out a delay
+/// or for the player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Before the first piece. Rotate and hold presses are kept for the
+    /// initial actions of the first piece and releases still go through,
+    /// other presses are ignored.
+    Countdown,
+    /// Entry delay (ARE), waiting for the next piece to spawn.
+    Spawning,
+    /// The piece is in the air.
+    Falling,
+    /// The piece rests on the stack while the lock delay runs.
+    Locking,
+    /// Full lines are shown before they are removed, so they can be
+    /// animated.
+    Clearing(Vec<usize>),
+    /// Keeps the state to resume, untouched.
+    Paused(Box<State>),
+    GameOver,
+}
+
+impl State {
+    /// Whether the game may go from this state to `next`.
+    pub fn can_become(&self, next: &State) -> bool {
+        use State::*;
+        match (self, next) {
+            (Paused(paused), next) => **paused == *next,
+            (GameOver, _) => false,
+            (state, Paused(paused)) => **paused == *state,
+            (Countdown, Spawning) => true,
+            (Spawning, Falling | GameOver) => true,
+            (Falling, Locking | GameOver) => true,
+            (Locking, Falling | Spawning | Clearing(_) | GameOver) => true,
+            (Clearing(_), Spawning) => true,
+            _ => false,
+        }
+    }
+
+    pub fn piece_in_play(&self) -> bool {
+        matches!(self, Self::Falling | Self::Locking)
+    }
+}
+
+/// Lengths of the delays of the game. All of them default to zero, the
+/// first piece spawns right away and the next one right after a lock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Delays {
+    /// Countdown before the first piece.
+    pub countdown: Duration,
+    /// Entry delay after a lock that cleared no lines.
+    pub are: Duration,
+    /// Entry delay after a line clear.
+    pub line_are: Duration,
+    /// How long cleared lines stay before the stack collapses.
+    pub line_clear: Duration,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(State::Countdown, State::Spawning, true)]
+    #[case(State::Countdown, State::Falling, false)]
+    #[case(State::Spawning, State::GameOver, true)]
+    #[case(State::Falling, State::Spawning, false)]
+    #[case(State::Locking, State::Clearing(vec![0]), true)]
+    #[case(State::Locking, State::GameOver, true)]
+    #[case(State::Clearing(vec![0]), State::Falling, false)]
+    #[case(State::Falling, State::Paused(Box::new(State::Falling)), true)]
+    #[case(State::Paused(Box::new(State::Falling)), State::Falling, true)]
+    #[case(State::Paused(Box::new(State::Falling)), State::Locking, false)]
+    #[case(State::Falling, State::Paused(Box::new(State::Locking)), false)]
+    #[case(State::GameOver, State::Paused(Box::new(State::GameOver)), false)]
+    fn transitions(
+        #[case] from: State,
+        #[case] to: State,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(from.can_become(&to), expected);
+    }
+}
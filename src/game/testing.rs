@@ -0,0 +1,29 @@
+//! Fixtures shared by the tests of games and of their replays.
+
+use super::{Game, Input, InputAction, KeyAction, TimedInput};
+
+pub(crate) fn timed(tick: u64, input: Input, action: KeyAction) -> TimedInput {
+    TimedInput {
+        tick,
+        action: InputAction::new(input, action),
+    }
+}
+
+/// Plays `ticks` ticks of `game`, pressing the next of `inputs` every
+/// `interval` ticks and releasing it `hold` ticks later.
+pub(crate) fn play_pressing(
+    game: &mut Game,
+    inputs: &[Input],
+    interval: u64,
+    hold: u64,
+    ticks: u64,
+) {
+    for tick in 0..ticks {
+        if tick % interval == 0 {
+            let input = inputs[(tick / interval) as usize % inputs.len()];
+            game.queue_input(timed(tick, input, KeyAction::Press));
+            game.queue_input(timed(tick + hold, input, KeyAction::Release));
+        }
+        game.step();
+    }
+}
@@ -40,44 +40,120 @@ impl Default for DeltaTime {
     }
 }
 
-#[derive(Default)]
+/// Simulation rate, in ticks per second. The game only ever advances by
+/// whole ticks, so it plays the same on any machine and at any frame rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TickRate(pub u32);
+
+impl TickRate {
+    pub const DEFAULT: Self = Self(60);
+
+    pub fn tick_length(&self) -> Duration {
+        Duration::from_secs(1) / self.0
+    }
+
+    /// Ticks closest to `duration`.
+    pub fn ticks(&self, duration: Duration) -> u32 {
+        let nanos = duration.as_nanos() * self.0 as u128;
+        let second = Duration::from_secs(1).as_nanos();
+        ((nanos + second / 2) / second)
+            .try_into()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+impl Default for TickRate {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Counts ticks up to a target.
+#[derive(Clone, Default)]
 pub struct Timer {
-    accumulator: Duration,
-    target: Duration,
+    elapsed: u32,
+    target: u32,
     just_finished: bool,
 }
 
 impl Timer {
-    pub fn new(target: Duration) -> Self {
+    pub fn new(target: u32) -> Self {
         Self {
-            accumulator: Duration::default(),
+            elapsed: 0,
             target,
             just_finished: false,
         }
     }
 
-    pub fn update(&mut self, delta_time: DeltaTime) {
-        self.update_duration(delta_time.get());
+    pub fn tick(&mut self) {
+        self.advance(1);
     }
 
-    pub fn update_duration(&mut self, delta: Duration) {
-        self.accumulator += delta;
-        self.just_finished = self.accumulator >= self.target;
+    /// Runs `ticks` ticks at once. A timer without target finishes even
+    /// when advanced by none.
+    pub fn advance(&mut self, ticks: u32) {
+        self.elapsed = self.elapsed.saturating_add(ticks);
+        self.just_finished = self.elapsed >= self.target;
         if self.just_finished {
-            self.accumulator -= self.target;
+            self.elapsed = 0;
         }
     }
 
-    pub fn set_target(&mut self, target: Duration) {
+    pub fn set_target(&mut self, target: u32) {
         self.target = target;
     }
 
     pub fn reset(&mut self) {
-        self.accumulator = Duration::default();
+        self.elapsed = 0;
         self.just_finished = false;
     }
 
+    /// How far the timer is towards its target, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        match self.target {
+            0 => 1.0,
+            target => (self.elapsed as f32 / target as f32).min(1.0),
+        }
+    }
+
     pub fn just_finished(&self) -> bool {
         self.just_finished
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(TickRate(60), 500, 30)]
+    #[case(TickRate(60), 35, 2)]
+    #[case(TickRate(60), 0, 0)]
+    #[case(TickRate(1000), 35, 35)]
+    fn ticks_from_duration(
+        #[case] rate: TickRate,
+        #[case] millis: u64,
+        #[case] ticks: u32,
+    ) {
+        assert_eq!(rate.ticks(Duration::from_millis(millis)), ticks);
+    }
+
+    #[test]
+    fn timer_counts_ticks() {
+        let mut timer = Timer::new(3);
+        timer.tick();
+        timer.tick();
+        assert!(!timer.just_finished());
+        assert!((timer.progress() - 2.0 / 3.0).abs() < 1e-6);
+        timer.tick();
+        assert!(timer.just_finished());
+        timer.tick();
+        assert!(!timer.just_finished());
+
+        let mut instant = Timer::new(0);
+        instant.advance(0);
+        assert!(instant.just_finished());
+    }
+}
@@ -1,17 +1,28 @@
+mod font;
 mod render_traits;
 mod sub_rect;
+mod viewer;
 
-use std::isize;
+use std::time::Duration;
 
 use cgmath::{ElementWise, EuclideanSpace, Point2, Vector2};
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::Canvas,
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
     video::Window,
 };
 
 use crate::{
-    engine::{Color as EngineColor, Coordinate, Engine, Matrix, Offset},
-    game::{DeltaTime, Game, Input, InputAction, KeyAction},
+    engine::{Color as EngineColor, Coordinate, Matrix, Offset},
+    frontend::{
+        Command, FrontendEvent, GameLoop, InputSource, Panel, Renderer, Report,
+        VisibleCell,
+    },
+    game::{Game, Input, InputAction, KeyAction, State},
+    replay::Replay,
 };
 
 use self::{
@@ -21,6 +32,8 @@ use self::{
 
 pub use crate::engine::{MoveKind, RotateKind};
 
+pub use self::viewer::view;
+
 const WINDOW_INIT_SIZE: Vector2<u32> = Vector2::new(1024, 1024);
 const BACKGROUND_COLOR: Color = Color::RGB(0x10, 0x10, 0x18);
 const GRID_COLOR: Color = Color::WHITE;
@@ -38,159 +51,445 @@ impl TryFrom<Keycode> for Input {
             Keycode::Z => Rotate(RotateKind::CounterClockwise),
             Keycode::X => Rotate(RotateKind::Clockwise),
             Keycode::C => Hold,
-            Keycode::Escape => todo!("Pause"),
+            Keycode::V => CycleHold,
+            Keycode::Escape => Pause,
 
             _ => return Err(()),
         })
     }
 }
 
-pub fn run(game: Game) {
+/// Plays `game`, recording it in `replay`, which is saved when the game
+/// ends or the window is closed. Reports are handed to `report` as soon as
+/// they are made.
+pub fn run(game: Game, replay: Replay, report: impl FnMut(Report)) {
     let sdl = sdl2::init().expect("SDL2 initialization failed");
-
-    let canvas = {
-        let video =
-            sdl.video().expect("SDL2 video subsystem aquisition failed");
-
-        let window = video
-            .window("rs-totris", WINDOW_INIT_SIZE.x, WINDOW_INIT_SIZE.y)
-            .position_centered()
-            .resizable()
-            .build()
-            .expect("Window creation failed");
-
-        window
-            .into_canvas()
-            .accelerated()
-            .present_vsync()
-            .build()
-            .expect("Canvas creation failed")
+    let mut renderer = SdlRenderer::new(open_window(&sdl));
+    let mut input = SdlInput {
+        events: sdl.event_pump().expect("Event pump aquisition failed"),
+        timer: sdl.timer().expect("SDL2 timer subsystem aquisition failed"),
     };
 
-    let events = sdl.event_pump().expect("Event pump aquisition failed");
+    //NOTE: the window leaves the terminal free to report right away
+    GameLoop::new(game, replay).run(&mut renderer, &mut input, report);
+}
 
-    game_loop(events, game, canvas);
+fn open_window(sdl: &sdl2::Sdl) -> Canvas<Window> {
+    let video = sdl.video().expect("SDL2 video subsystem aquisition failed");
+
+    let window = video
+        .window("rs-totris", WINDOW_INIT_SIZE.x, WINDOW_INIT_SIZE.y)
+        .position_centered()
+        .resizable()
+        .build()
+        .expect("Window creation failed");
+
+    window
+        .into_canvas()
+        .accelerated()
+        .present_vsync()
+        .build()
+        .expect("Canvas creation failed")
 }
 
-fn game_loop(
-    mut events: sdl2::EventPump,
-    mut game: Game,
-    mut canvas: Canvas<Window>,
-) {
-    let mut delta_time = DeltaTime::new();
+/// Keyboard input from SDL events, aged by their timestamp.
+pub struct SdlInput {
+    events: sdl2::EventPump,
+    timer: sdl2::TimerSubsystem,
+}
 
-    // delta_time -> inputs -> game_logic -> rendering
-    loop {
-        delta_time.update();
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> Vec<FrontendEvent> {
+        let now = self.timer.ticks();
+        let age = |timestamp: u32| {
+            Duration::from_millis(now.saturating_sub(timestamp).into())
+        };
+        let input = |key, action, timestamp| {
+            Input::try_from(key).ok().map(|input| FrontendEvent::Input {
+                action: InputAction::new(input, action),
+                age: age(timestamp),
+            })
+        };
+
+        self.events
+            .poll_iter()
+            .filter_map(|event| match event {
+                Event::Quit { .. } => {
+                    Some(FrontendEvent::Command(Command::Quit))
+                }
+
+                //NOTE: fumen export of the board / whole game
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    repeat: false,
+                    ..
+                } => Some(FrontendEvent::Command(Command::ExportBoard)),
 
-        for event in events.poll_iter() {
-            match event {
-                Event::Quit { .. } => return,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => Some(FrontendEvent::Command(Command::ExportHistory)),
 
                 Event::KeyDown {
+                    timestamp,
                     keycode: Some(key),
                     repeat: false,
                     ..
-                } => {
-                    if let Ok(input) = Input::try_from(key) {
-                        game.handle_input(dbg!(InputAction::new(
-                            input,
-                            KeyAction::Press,
-                        )));
-                    };
-                }
+                } => input(key, KeyAction::Press, timestamp),
 
                 Event::KeyUp {
-                    keycode: Some(key), ..
-                } => {
-                    if let Ok(input) = Input::try_from(key) {
-                        game.handle_input(dbg!(InputAction::new(
-                            input,
-                            KeyAction::Release,
-                        )));
-                    };
-                }
+                    timestamp,
+                    keycode: Some(key),
+                    ..
+                } => input(key, KeyAction::Release, timestamp),
 
-                _ => {}
-            }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Where things go in the window, laid out anew each frame to follow its
+/// size.
+#[derive(Clone, Copy)]
+struct Layout {
+    matrix: SubRect,
+    up_next: SubRect,
+    hold: SubRect,
+    queue: SubRect,
+    score: SubRect,
+}
+
+impl Layout {
+    fn of(viewport: Rect) -> Self {
+        let ui_square = SubRect::absolute(viewport, (1.0, 1.0), None);
+
+        let matrix = ui_square
+            .sub_rect((0.5, 1.0), None)
+            .sub_rect((7.0 / 8.0, 7.0 / 8.0), None);
+
+        let up_next = ui_square
+            .sub_rect((0.25, 0.25), Some((Align::Far, Align::Near)))
+            .sub_rect((0.75, 0.75), None);
+
+        let hold = ui_square
+            .sub_rect((0.25, 0.25), Some((Align::Near, Align::Near)))
+            .sub_rect((0.75, 0.75), None);
+
+        let queue = ui_square
+            .sub_rect((0.25, 0.75), Some((Align::Far, Align::Far)))
+            .sub_rect(
+                (5.0 / 8.0, 23.0 / 24.0),
+                Some((Align::Center, Align::Near)),
+            );
+
+        let score = ui_square
+            .sub_rect((0.25, 11.0 / 16.0), Some((Align::Near, Align::Far)))
+            .sub_rect(
+                (7.0 / 8.0, 8.0 / 11.0),
+                Some((Align::Center, Align::Near)),
+            );
+
+        Self {
+            matrix,
+            up_next,
+            hold,
+            queue,
+            score,
         }
+    }
 
-        game.update(delta_time);
-        draw(&mut canvas, &game.engine);
-        // println!("FPS: {}", delta_time.fps());
+    fn panel(&self, panel: Panel) -> Rect {
+        Rect::from(match panel {
+            Panel::Hold => self.hold,
+            Panel::Next => self.queue,
+            Panel::Score => self.score,
+        })
     }
 }
 
-fn draw(canvas: &mut Canvas<Window>, engine: &Engine) {
-    canvas.set_draw_color(BACKGROUND_COLOR);
-    canvas.clear();
+/// Draws on the window canvas.
+pub struct SdlRenderer {
+    canvas: Canvas<Window>,
+    layout: Layout,
+    block_size: u32,
+}
+
+impl SdlRenderer {
+    pub fn new(canvas: Canvas<Window>) -> Self {
+        let layout = Layout::of(canvas.viewport());
+        Self {
+            canvas,
+            layout,
+            block_size: 1,
+        }
+    }
 
-    //NOTE: draw graphics from here on out
+    /// The canvas, to draw more on top of the game.
+    pub fn canvas_mut(&mut self) -> &mut Canvas<Window> {
+        &mut self.canvas
+    }
 
-    let viewport = canvas.viewport();
-    let ui_square = SubRect::absolute(viewport, (1.0, 1.0), None);
+    fn cell_context(&mut self) -> CellDrawContext<'_> {
+        CellDrawContext {
+            origin: self.layout.matrix.bottom_left(),
+            dims: self.layout.matrix.size(),
+            block_size: self.block_size,
+            canvas: &mut self.canvas,
+        }
+    }
+}
 
-    let matrix = ui_square
-        .sub_rect((0.5, 1.0), None)
-        .sub_rect((7.0 / 8.0, 7.0 / 8.0), None);
+impl Renderer for SdlRenderer {
+    fn clear(&mut self) {
+        self.layout = Layout::of(self.canvas.viewport());
+        self.canvas.set_draw_color(BACKGROUND_COLOR);
+        self.canvas.clear();
+
+        // NOTE: UI drawing
+        let Layout {
+            matrix,
+            up_next,
+            hold,
+            queue,
+            score,
+        } = self.layout;
+        self.canvas.set_draw_color(PLACEHOLDER_COLOR);
+        for sub_rect in &[matrix, up_next, hold, queue, score] {
+            self.canvas
+                .fill_rect(Rect::from(sub_rect))
+                .expect("Fatal redering error");
+        }
+    }
 
-    let up_next = ui_square
-        .sub_rect((0.25, 0.25), Some((Align::Far, Align::Near)))
-        .sub_rect((0.75, 0.75), None);
+    fn draw_matrix(&mut self, cells: &[VisibleCell], block_size: usize) {
+        self.block_size = block_size as u32;
+        let mut cell_ctx = self.cell_context();
+        for &(coord, cell) in cells {
+            let cell_color = cell.map(|(color, opacity)| {
+                faded(color.screen_color(), PLACEHOLDER_COLOR, opacity)
+            });
+            cell_ctx.try_draw_cell(coord, cell_color, true)
+        }
+    }
 
-    let hold = ui_square
-        .sub_rect((0.25, 0.25), Some((Align::Near, Align::Near)))
-        .sub_rect((0.75, 0.75), None);
+    //NOTE: cleared lines flash, then fade out
+    fn draw_cleared_lines(&mut self, lines: &[usize], progress: f32) {
+        let color = faded(GRID_COLOR, PLACEHOLDER_COLOR, 1.0 - progress);
+        let mut cell_ctx = self.cell_context();
+        for &y in lines {
+            for x in 0..Matrix::WIDTH {
+                cell_ctx.draw_cell(Coordinate::new(x, y), color, false);
+            }
+        }
+    }
 
-    let queue = ui_square
-        .sub_rect((0.25, 0.75), Some((Align::Far, Align::Far)))
-        .sub_rect((5.0 / 8.0, 23.0 / 24.0), Some((Align::Center, Align::Near)));
+    fn draw_piece(&mut self, cells: &[Coordinate], color: EngineColor) {
+        let mut cell_ctx = self.cell_context();
+        for &coord in cells {
+            cell_ctx.draw_cell(coord, color.screen_color(), false);
+            cell_ctx.draw_cell(coord, GRID_COLOR, true);
+        }
+    }
 
-    let score = ui_square
-        .sub_rect((0.25, 11.0 / 16.0), Some((Align::Near, Align::Far)))
-        .sub_rect((7.0 / 8.0, 8.0 / 11.0), Some((Align::Center, Align::Near)));
+    fn draw_ghost(&mut self, cells: &[Coordinate], color: EngineColor) {
+        let color = faded(color.screen_color(), PLACEHOLDER_COLOR, 0.5);
+        let mut cell_ctx = self.cell_context();
+        for &coord in cells {
+            cell_ctx.draw_cell(coord, color, true);
+        }
+    }
 
-    // NOTE: UI drawing
-    canvas.set_draw_color(PLACEHOLDER_COLOR);
-    for sub_rect in &[matrix, up_next, hold, queue, score] {
-        canvas
-            .fill_rect(Rect::from(sub_rect))
-            .expect("Fatal redering error");
+    //NOTE: the next piece gets a box of its own, the rest are queued
+    fn draw_panel(
+        &mut self,
+        panel: Panel,
+        pieces: &[(Vec<Offset>, EngineColor)],
+    ) {
+        match (panel, pieces) {
+            (Panel::Next, [next, queue @ ..]) => {
+                let up_next = Rect::from(self.layout.up_next);
+                draw_slots(
+                    &mut self.canvas,
+                    up_next,
+                    std::slice::from_ref(next),
+                );
+                draw_slots(&mut self.canvas, self.layout.panel(panel), queue);
+            }
+            _ => draw_slots(&mut self.canvas, self.layout.panel(panel), pieces),
+        }
     }
 
-    //NOTE: Matrix content rendering
-    {
-        let mut cell_ctx = CellDrawContext {
-            origin: matrix.bottom_left(),
-            dims: matrix.size(),
-            canvas,
-        };
+    fn draw_text(&mut self, panel: Panel, text: &str) {
+        let area = self.layout.panel(panel);
+        let columns = text.lines().map(str::len).max().unwrap_or(0) as u32;
+        let rows = text.lines().count() as u32;
+        if columns == 0 {
+            return;
+        }
 
-        for (coord, cell_color) in engine.cells() {
-            cell_ctx.try_draw_cell(coord, cell_color, true)
+        //NOTE: a pixel of space around each glyph, and a margin around all
+        let pixel = (area.width() / ((font::WIDTH + 1) * columns + 2))
+            .min(area.height() / ((font::HEIGHT + 1) * rows + 2))
+            .max(1);
+        self.canvas.set_draw_color(GRID_COLOR);
+        for (row, line) in text.lines().enumerate() {
+            let y = 1 + row as u32 * (font::HEIGHT + 1);
+            let origin = Point::new(
+                area.x() + pixel as i32,
+                area.y() + (y * pixel) as i32,
+            );
+            draw_line(&mut self.canvas, origin, pixel, line);
         }
+    }
 
-        if let Some((cursor_cells, color, _, _)) = engine.cursor_info() {
-            for coord in cursor_cells {
-                cell_ctx.draw_cell(coord, color.screen_color(), false);
-                cell_ctx.draw_cell(coord, GRID_COLOR, true);
+    //NOTE: the matrix is hidden while paused
+    fn draw_state(&mut self, state: &State, progress: f32) {
+        let area = Rect::from(self.layout.matrix);
+        match state {
+            State::Paused(_) => {
+                self.canvas.set_draw_color(PLACEHOLDER_COLOR);
+                self.canvas.fill_rect(area).expect("Fatal redering error");
+
+                let bar = Rect::from_center(
+                    area.center(),
+                    area.width() / 16,
+                    area.width() / 4,
+                );
+                self.canvas.set_draw_color(GRID_COLOR);
+                for side in [-1, 1] {
+                    let offset = side * bar.width() as i32;
+                    let mut bar = bar;
+                    bar.offset(offset, 0);
+                    self.canvas.fill_rect(bar).expect("Fatal redering error");
+                }
             }
+            State::Countdown => {
+                let width = area.width() as f32 * (1.0 - progress);
+                let bar = Rect::from_center(
+                    area.center(),
+                    width as u32,
+                    area.width() / 32,
+                );
+                self.canvas.set_draw_color(GRID_COLOR);
+                self.canvas.fill_rect(bar).expect("Fatal redering error");
+            }
+            _ => {}
         }
     }
 
-    //NOTE: Hold piece rendering
-    {
-        if let Some((coords, color)) = engine.held_cursor_info() {
-            todo!("draw held piece")
+    fn present(&mut self) {
+        self.canvas.present();
+    }
+}
+
+/// Draws a line of `text` from its top left corner, in dots of `pixel`
+/// with a dot of space between glyphs.
+fn draw_line(
+    canvas: &mut Canvas<Window>,
+    origin: Point,
+    pixel: u32,
+    text: &str,
+) {
+    for (column, c) in text.chars().enumerate() {
+        let Some(glyph) = font::glyph(c) else {
+            continue;
+        };
+        let x = column as u32 * (font::WIDTH + 1);
+        for (dy, bits) in glyph.iter().enumerate() {
+            for dx in 0..font::WIDTH {
+                if bits & (1 << (font::WIDTH - 1 - dx)) == 0 {
+                    continue;
+                }
+                let dot = Rect::new(
+                    origin.x() + ((x + dx) * pixel) as i32,
+                    origin.y() + (dy as u32 * pixel) as i32,
+                    pixel,
+                    pixel,
+                );
+                canvas.fill_rect(dot).expect("Fatal redering error");
+            }
         }
     }
+}
+
+/// Draws pieces stacked top to bottom in `area`, one slot each.
+fn draw_slots(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    pieces: &[(Vec<Offset>, EngineColor)],
+) {
+    let slot_height = area.height() / pieces.len().max(1) as u32;
+    for (i, (offsets, color)) in pieces.iter().enumerate() {
+        let slot = Rect::new(
+            area.x(),
+            area.y() + (i as u32 * slot_height) as i32,
+            area.width(),
+            slot_height,
+        );
+        draw_preview(canvas, slot, offsets, color.screen_color());
+    }
+}
+
+/// Draws a piece outside of the matrix, centered in `area`.
+fn draw_preview(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    offsets: &[Offset],
+    color: Color,
+) {
+    let (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) = (
+        offsets.iter().map(|offset| offset.x).min(),
+        offsets.iter().map(|offset| offset.x).max(),
+        offsets.iter().map(|offset| offset.y).min(),
+        offsets.iter().map(|offset| offset.y).max(),
+    ) else {
+        return;
+    };
+
+    let extent = Vector2::new(max_x - min_x + 1, max_y - min_y + 1)
+        .cast::<u32>()
+        .expect("Should be positive");
+    //NOTE: leave room for the largest tetromino, and one cell of margin
+    let cells = extent.x.max(extent.y).max(4) + 1;
+    let cell_size = area.width().min(area.height()) / cells;
+    let origin = area.center()
+        - sdl2::rect::Point::new(
+            (extent.x * cell_size / 2) as i32,
+            (extent.y * cell_size / 2) as i32,
+        );
+
+    for offset in offsets {
+        let cell_rect = Rect::new(
+            origin.x() + ((offset.x - min_x) as u32 * cell_size) as i32,
+            origin.y() + ((max_y - offset.y) as u32 * cell_size) as i32,
+            cell_size,
+            cell_size,
+        );
+        canvas.set_draw_color(color);
+        canvas.fill_rect(cell_rect).expect("Fatal redering error");
+        canvas.set_draw_color(GRID_COLOR);
+        canvas.draw_rect(cell_rect).expect("Fatal redering error");
+    }
+}
 
-    canvas.present();
+/// Blends `color` into `background`, fully showing it at an opacity of 1.
+fn faded(color: Color, background: Color, opacity: f32) -> Color {
+    let mix = |fg: u8, bg: u8| {
+        (bg as f32 + (fg as f32 - bg as f32) * opacity).round() as u8
+    };
+    Color::RGB(
+        mix(color.r, background.r),
+        mix(color.g, background.g),
+        mix(color.b, background.b),
+    )
 }
 
 pub struct CellDrawContext<'canvas> {
     origin: Point2<i32>,
     dims: Vector2<u32>,
+    block_size: u32,
     canvas: &'canvas mut Canvas<Window>,
 }
 
@@ -201,11 +500,11 @@ impl CellDrawContext<'_> {
     fn try_draw_cell(
         &mut self,
         coord: Coordinate,
-        cell_color: Option<EngineColor>,
+        cell_color: Option<Color>,
         draw_wire_frame: bool,
     ) {
         if let Some(cell_color) = cell_color {
-            self.draw_cell(coord, cell_color.screen_color(), false);
+            self.draw_cell(coord, cell_color, false);
         };
 
         if draw_wire_frame {
@@ -218,15 +517,26 @@ impl CellDrawContext<'_> {
     ///       internal coordinate system.
     ///       In addition, we need to scale the coordinates to fit the
     ///       size (in pixels) of the ui matrix. This is important
+    ///
+    /// In big mode, cells are drawn per block: only the bottom left cell of
+    /// each block is drawn, covering the whole block.
     fn draw_cell(&mut self, coord: Coordinate, color: Color, wire_frame: bool) {
         let coord = coord.to_vec().cast::<u32>().expect("Should be safe");
+        if !coord.x.is_multiple_of(self.block_size)
+            || !coord.y.is_multiple_of(self.block_size)
+        {
+            return;
+        }
+
+        let coord = coord / self.block_size;
+        let cell_count = Self::CELL_COUNT / self.block_size;
         let this = (coord + Vector2::new(0, 1))
             .mul_element_wise(self.dims)
-            .div_element_wise(Self::CELL_COUNT);
+            .div_element_wise(cell_count);
 
         let next = (coord + Vector2::new(1, 0))
             .mul_element_wise(self.dims)
-            .div_element_wise(Self::CELL_COUNT);
+            .div_element_wise(cell_count);
 
         let cell_rect = Rect::new(
             self.origin.x + this.x as i32,
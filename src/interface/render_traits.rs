@@ -16,6 +16,7 @@ impl ScreenColor for EngineColor {
             EngineColor::Blue => SdlColor::RGB(0x34, 0x65, 0xa4),
             EngineColor::Green => SdlColor::RGB(0x73, 0xd2, 0x16),
             EngineColor::Red => SdlColor::RGB(0xef, 0x29, 0x29),
+            EngineColor::Gray => SdlColor::RGB(0x88, 0x8a, 0x85),
         }
     }
 }
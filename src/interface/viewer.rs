@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
+    video::Window,
+};
+
+use crate::{
+    engine::{MoveKind, RotateKind},
+    frontend::{draw_game, Renderer, MAX_FRAME_TIME},
+    game::{DeltaTime, Input, KeyAction},
+    replay::{Playback, Replay, INPUT_CODES},
+};
+
+use super::{
+    draw_line, font, open_window, SdlRenderer, GRID_COLOR, PLACEHOLDER_COLOR,
+};
+
+/// Playback speeds, stepped through with the up and down keys.
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED: usize = 2;
+/// How far the left and right keys seek.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+const PRESS_COLOR: Color = Color::RGB(0xff, 0xcc, 0x33);
+
+/// Plays `replay` back. Space pauses, `.` steps a single tick, up and down
+/// change the speed, left and right seek, as does clicking the timeline.
+pub fn view(replay: Replay) {
+    let sdl = sdl2::init().expect("SDL2 initialization failed");
+    let renderer = SdlRenderer::new(open_window(&sdl));
+    let events = sdl.event_pump().expect("Event pump aquisition failed");
+
+    let playback = Playback::new(replay).expect("Invalid ruleset");
+    viewer_loop(events, playback, renderer);
+}
+
+fn viewer_loop(
+    mut events: sdl2::EventPump,
+    mut playback: Playback,
+    mut renderer: SdlRenderer,
+) {
+    let tick_rate = playback.game().tick_rate();
+    let tick_length = tick_rate.tick_length();
+    let seek_step = tick_rate.ticks(SEEK_STEP) as u64;
+    let mut delta_time = DeltaTime::new();
+    let mut accumulator = Duration::ZERO;
+    let mut speed = NORMAL_SPEED;
+    let mut paused = false;
+
+    loop {
+        delta_time.update();
+
+        for event in events.poll_iter() {
+            let tick = playback.game().tick();
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return,
+
+                Event::KeyDown {
+                    keycode: Some(key),
+                    repeat,
+                    ..
+                } => match key {
+                    Keycode::Space if !repeat => paused = !paused,
+                    Keycode::Period => {
+                        paused = true;
+                        playback.step();
+                    }
+                    Keycode::Up => speed = (speed + 1).min(SPEEDS.len() - 1),
+                    Keycode::Down => speed = speed.saturating_sub(1),
+                    Keycode::Left => {
+                        playback.seek(tick.saturating_sub(seek_step))
+                    }
+                    Keycode::Right => playback.seek(tick + seek_step),
+                    Keycode::Home => playback.seek(0),
+                    Keycode::End => playback.seek(playback.length()),
+                    _ => {}
+                },
+
+                Event::MouseButtonDown { x, y, .. } => {
+                    let viewport = renderer.canvas_mut().viewport();
+                    let timeline = timeline_area(viewport);
+                    if timeline.contains_point((x, y)) {
+                        let progress =
+                            (x - timeline.x()) as f64 / timeline.width() as f64;
+                        let length = playback.length() as f64;
+                        playback.seek((progress * length).round() as u64);
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        //NOTE: the speed scales real time, the game still runs in ticks
+        match paused {
+            true => accumulator = Duration::ZERO,
+            false => {
+                accumulator +=
+                    delta_time.get().min(MAX_FRAME_TIME).mul_f32(SPEEDS[speed])
+            }
+        }
+        while accumulator >= tick_length {
+            playback.step();
+            accumulator -= tick_length;
+        }
+
+        draw_game(&mut renderer, playback.game());
+        draw_controls(renderer.canvas_mut(), &playback, speed);
+        renderer.present();
+    }
+}
+
+/// Size of the controls drawn along the bottom of the window.
+fn control_size(viewport: Rect) -> u32 {
+    (viewport.height() / 40).max(4)
+}
+
+fn timeline_area(viewport: Rect) -> Rect {
+    let size = control_size(viewport);
+    Rect::new(
+        size as i32,
+        viewport.height().saturating_sub(2 * size) as i32,
+        viewport.width().saturating_sub(2 * size).max(1),
+        size,
+    )
+}
+
+/// Draws the timeline, the inputs of the current tick, and the speed.
+fn draw_controls(
+    canvas: &mut Canvas<Window>,
+    playback: &Playback,
+    speed: usize,
+) {
+    let viewport = canvas.viewport();
+    let size = control_size(viewport);
+
+    let timeline = timeline_area(viewport);
+    let progress = match playback.length() {
+        0 => 1.0,
+        length => playback.game().tick() as f64 / length as f64,
+    };
+    let played = Rect::new(
+        timeline.x(),
+        timeline.y(),
+        (timeline.width() as f64 * progress).round().max(1.0) as u32,
+        timeline.height(),
+    );
+    canvas.set_draw_color(PLACEHOLDER_COLOR);
+    canvas.fill_rect(timeline).expect("Fatal redering error");
+    canvas.set_draw_color(GRID_COLOR);
+    canvas.fill_rect(played).expect("Fatal redering error");
+
+    //NOTE: one label per input, lit while held, flashing when pressed
+    let row = timeline.y() - 2 * size as i32;
+    let pixel = (size / font::HEIGHT).max(1);
+    let held = playback.held_inputs();
+    let pressed = playback.last_inputs();
+    for (i, (input, _)) in INPUT_CODES.iter().enumerate() {
+        let color = if pressed.iter().any(|timed| {
+            timed.action.input == *input
+                && timed.action.action == KeyAction::Press
+        }) {
+            PRESS_COLOR
+        } else if held.contains(input) {
+            GRID_COLOR
+        } else {
+            PLACEHOLDER_COLOR
+        };
+        let origin =
+            Point::new(timeline.x() + (i as u32 * 3 * size) as i32, row);
+        canvas.set_draw_color(color);
+        draw_line(canvas, origin, pixel, label(*input));
+    }
+
+    for i in 0..SPEEDS.len() {
+        let pip = Rect::new(
+            timeline.right() - ((SPEEDS.len() - i) as u32 * 2 * size) as i32
+                + size as i32,
+            row,
+            size,
+            size,
+        );
+        canvas.set_draw_color(match i <= speed {
+            true => GRID_COLOR,
+            false => PLACEHOLDER_COLOR,
+        });
+        canvas.fill_rect(pip).expect("Fatal redering error");
+    }
+}
+
+/// Short name of an input, as drawn above the timeline.
+fn label(input: Input) -> &'static str {
+    match input {
+        Input::Move(MoveKind::Left) => "L",
+        Input::Move(MoveKind::Right) => "R",
+        Input::Rotate(RotateKind::Clockwise) => "CW",
+        Input::Rotate(RotateKind::CounterClockwise) => "CCW",
+        Input::SoftDrop => "SD",
+        Input::HardDrop => "HD",
+        Input::Hold => "H",
+        Input::CycleHold => "CH",
+        Input::Pause => "P",
+    }
+}
@@ -0,0 +1,22 @@
+//! A guideline-style falling block game: the [`Engine`] owns the matrix
+//! and the pieces, the [`Game`] runs it in fixed ticks from timestamped
+//! inputs. Neither needs a window, the SDL frontend is behind the `sdl`
+//! feature and the terminal one behind the `tui` feature. Both are off by
+//! default, the `totris` binary plays with whichever are built in.
+
+pub mod engine;
+pub mod frontend;
+pub mod game;
+#[cfg(feature = "sdl")]
+pub mod interface;
+pub mod replay;
+pub mod ruleset;
+#[cfg(feature = "tui")]
+pub mod terminal;
+
+pub use crate::{
+    engine::{Engine, Matrix},
+    game::{Game, Handling, Input, InputAction, KeyAction, TimedInput},
+    replay::Replay,
+    ruleset::Ruleset,
+};
@@ -1,13 +1,108 @@
-// #![allow(dead_code)]
-#![feature(array_chunks, is_sorted)]
+use std::process::ExitCode;
 
-mod engine;
-mod game;
-mod interface;
+#[cfg(feature = "sdl")]
+use totris::interface;
+#[cfg(feature = "tui")]
+use totris::terminal;
+use totris::{frontend::Report, Game, Handling, Replay, Ruleset};
 
-use crate::{engine::Engine, game::Game};
+/// The frontend played without `--frontend`, the window when there is one.
+const DEFAULT_FRONTEND: &str = match cfg!(feature = "sdl") {
+    true => "sdl",
+    false => "tui",
+};
 
-fn main() {
-    println!("Hello, world!");
-    interface::run(Game::new(Engine::new()));
+fn main() -> ExitCode {
+    let mut options = Vec::new();
+    let mut handling = Handling::default();
+    let mut seed = None;
+    let mut initial_actions = false;
+    let mut frontend = DEFAULT_FRONTEND.to_owned();
+    for arg in std::env::args().skip(1) {
+        if let Some(path) = arg.strip_prefix("--replay=") {
+            return match Replay::load(path) {
+                Ok(replay) => view(replay),
+                Err(error) => fail(format!("Invalid replay: {error}")),
+            };
+        } else if let Some(number) = arg.strip_prefix("--seed=") {
+            match number.parse() {
+                Ok(number) => seed = Some(number),
+                Err(_) => return fail(format!("Invalid seed {number}")),
+            }
+        } else if let Some(path) = arg.strip_prefix("--settings=") {
+            match Handling::load(path) {
+                Ok(loaded) => handling = loaded,
+                Err(error) => {
+                    return fail(format!("Invalid settings file: {error}"))
+                }
+            }
+        } else if arg == "--irs" {
+            initial_actions = true;
+        } else if let Some(name) = arg.strip_prefix("--frontend=") {
+            frontend = name.to_owned();
+        } else {
+            options.push(arg);
+        }
+    }
+
+    let Some(run) = find_frontend(&frontend) else {
+        return fail(format!(
+            "Frontend {frontend} is not available in this build"
+        ));
+    };
+
+    //NOTE: a shortcut for the `irs` setting, whatever the settings file
+    handling.initial_actions |= initial_actions;
+    let seed = seed.unwrap_or_else(rand::random);
+    let ruleset = match Ruleset::from_options(options) {
+        Ok(ruleset) => ruleset,
+        Err(error) => return fail(format!("Invalid options: {error}")),
+    };
+    let replay = Replay::new(ruleset, handling, seed);
+    let game = match replay.new_game() {
+        Ok(game) => game,
+        Err(error) => return fail(format!("Invalid options: {error}")),
+    };
+    run(game, replay, print_report);
+    ExitCode::SUCCESS
+}
+
+/// Plays a game, handing its reports to the callback.
+type Frontend = fn(Game, Replay, fn(Report));
+
+/// Looks up a frontend built in by name.
+fn find_frontend(name: &str) -> Option<Frontend> {
+    match name {
+        #[cfg(feature = "sdl")]
+        "sdl" => Some(interface::run),
+        #[cfg(feature = "tui")]
+        "tui" => Some(terminal::run),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn view(replay: Replay) -> ExitCode {
+    interface::view(replay);
+    ExitCode::SUCCESS
+}
+
+//NOTE: only the SDL frontend has a replay viewer
+#[cfg(not(feature = "sdl"))]
+fn view(_: Replay) -> ExitCode {
+    fail("Viewing replays needs the sdl frontend, not in this build".into())
+}
+
+/// Prints what the frontend reports, on stderr when it is an error.
+fn print_report(report: Report) {
+    match report.is_error() {
+        true => eprintln!("{report}"),
+        false => println!("{report}"),
+    }
+}
+
+/// Reports bad user input, for `main` to return.
+fn fail(message: String) -> ExitCode {
+    eprintln!("{message}");
+    ExitCode::FAILURE
 }
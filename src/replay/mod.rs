@@ -0,0 +1,608 @@
+//! Replays: everything needed to play a game again, the ruleset, the
+//! handling settings, the seed and every input at the tick it was handled,
+//! along with how the game ended to check it plays out the same.
+//!
+//! They are saved as `.totr` files, in little endian, where `varint` is an
+//! unsigned LEB128 number and `text` a varint length followed by UTF-8:
+//!
+//! ```text
+//! magic     4 bytes  "TOTR"
+//! version   u16      format version, see FORMAT_VERSION
+//! engine    text     version of totris that wrote the file
+//! options   varint   count, then each option as text
+//! files     varint   count, then each file named by an option as its
+//!                    path and contents, both text
+//! handling  text     settings, in the settings file format
+//! seed      u64
+//! inputs    varint   count, then for each input:
+//!   tick    varint   ticks since the previous input
+//!   event   u8       input code << 1, plus 1 for a release
+//! score     varint
+//! lines     varint
+//! ticks     varint   length of the game, paused ticks included
+//! checksum  u64      checksum of the final board
+//! ```
+
+mod playback;
+mod script;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    engine::{MoveKind, RotateKind},
+    game::{
+        Game, Handling, Input, InputAction, KeyAction, ParseHandlingError,
+        TimedInput,
+    },
+    ruleset::{Ruleset, RulesetError},
+};
+
+pub use self::{playback::Playback, script::ParseScriptError};
+
+const MAGIC: &[u8; 4] = b"TOTR";
+/// Bumped on every change to the format.
+const FORMAT_VERSION: u16 = 1;
+const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    /// Version of totris the game was played on.
+    pub engine_version: String,
+    pub ruleset: Ruleset,
+    pub handling: Handling,
+    pub seed: u64,
+    pub inputs: Vec<TimedInput>,
+    pub summary: Summary,
+}
+
+/// How a recorded game ended.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub score: u32,
+    pub lines: u32,
+    pub ticks: u64,
+    /// Checksum of the locked cells, see [`crate::engine::Engine`].
+    pub checksum: u64,
+}
+
+#[derive(Debug)]
+pub enum ParseReplayError {
+    Unreadable {
+        path: String,
+        source: io::Error,
+    },
+    NotAReplay,
+    /// Made by a newer totris.
+    UnsupportedVersion {
+        version: u16,
+    },
+    Truncated,
+    InvalidText,
+    /// A varint longer than 64 bits, or a score or line count past `u32`.
+    InvalidNumber,
+    InvalidInput {
+        index: usize,
+    },
+    InvalidHandling(ParseHandlingError),
+    /// The recorded ruleset cannot set a game up.
+    InvalidRuleset(RulesetError),
+    TrailingBytes,
+}
+
+impl fmt::Display for ParseReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable { path, source } => {
+                write!(f, "cannot read {path}: {source}")
+            }
+            Self::NotAReplay => write!(f, "not a replay"),
+            Self::UnsupportedVersion { version } => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::Truncated => write!(f, "truncated replay"),
+            Self::InvalidText => write!(f, "invalid UTF-8 text"),
+            Self::InvalidNumber => write!(f, "invalid number"),
+            Self::InvalidInput { index } => write!(f, "invalid input {index}"),
+            Self::InvalidHandling(error) => {
+                write!(f, "invalid handling: {error}")
+            }
+            Self::InvalidRuleset(error) => {
+                write!(f, "invalid ruleset: {error}")
+            }
+            Self::TrailingBytes => write!(f, "trailing bytes after the replay"),
+        }
+    }
+}
+
+impl std::error::Error for ParseReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unreadable { source, .. } => Some(source),
+            Self::InvalidHandling(error) => Some(error),
+            Self::InvalidRuleset(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Summary {
+    pub fn of(game: &Game) -> Self {
+        Self {
+            score: game.engine.score(),
+            lines: game.engine.lines(),
+            ticks: game.tick(),
+            checksum: game.engine.checksum(),
+        }
+    }
+}
+
+impl Replay {
+    /// A replay of a game about to start, without inputs yet.
+    pub fn new(ruleset: Ruleset, handling: Handling, seed: u64) -> Self {
+        Self {
+            engine_version: ENGINE_VERSION.to_owned(),
+            ruleset,
+            handling,
+            seed,
+            inputs: Vec::new(),
+            summary: Summary::default(),
+        }
+    }
+
+    /// Sets the recorded game up, before any input.
+    pub fn new_game(&self) -> Result<Game, RulesetError> {
+        self.ruleset.new_game(self.seed, self.handling)
+    }
+
+    /// Takes the inputs the game handled so far, and how it stands.
+    pub fn record(&mut self, game: &Game) {
+        self.inputs = game.handled_inputs().to_vec();
+        self.summary = Summary::of(game);
+    }
+
+    pub fn load(path: &str) -> Result<Self, ParseReplayError> {
+        let bytes = std::fs::read(path).map_err(|source| {
+            ParseReplayError::Unreadable {
+                path: path.into(),
+                source,
+            }
+        })?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Writes the replay in `dir`, named after the time it is saved at.
+    /// Replays never overwrite each other, a name already taken gets a
+    /// counter.
+    pub fn save(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let name = format!("{secs}-{}", self.seed);
+        let mut copy = 0;
+        loop {
+            let path = match copy {
+                0 => dir.join(format!("{name}.totr")),
+                _ => dir.join(format!("{name}-{copy}.totr")),
+            };
+            match File::create_new(&path) {
+                Ok(mut file) => {
+                    file.write_all(&self.to_bytes())?;
+                    return Ok(path);
+                }
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    copy += 1
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        write_text(&mut bytes, &self.engine_version);
+        write_varint(&mut bytes, self.ruleset.options.len() as u64);
+        for option in &self.ruleset.options {
+            write_text(&mut bytes, option);
+        }
+        write_varint(&mut bytes, self.ruleset.files.len() as u64);
+        for (path, contents) in &self.ruleset.files {
+            write_text(&mut bytes, path);
+            write_text(&mut bytes, contents);
+        }
+        write_text(&mut bytes, &self.handling.to_string());
+        bytes.extend(self.seed.to_le_bytes());
+
+        write_varint(&mut bytes, self.inputs.len() as u64);
+        let mut previous = 0;
+        for TimedInput { tick, action } in &self.inputs {
+            write_varint(&mut bytes, tick - previous);
+            previous = *tick;
+            let release = (action.action == KeyAction::Release) as u8;
+            bytes.push(input_code(action.input) << 1 | release);
+        }
+
+        let Summary {
+            score,
+            lines,
+            ticks,
+            checksum,
+        } = self.summary;
+        write_varint(&mut bytes, score as u64);
+        write_varint(&mut bytes, lines as u64);
+        write_varint(&mut bytes, ticks);
+        bytes.extend(checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a replay, checking its ruleset sets a game up.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseReplayError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ParseReplayError::NotAReplay);
+        }
+        let replay = match reader.u16()? {
+            FORMAT_VERSION => reader.replay()?,
+            version => {
+                return Err(ParseReplayError::UnsupportedVersion { version })
+            }
+        };
+        if !reader.0.is_empty() {
+            return Err(ParseReplayError::TrailingBytes);
+        }
+        replay
+            .new_game()
+            .map_err(ParseReplayError::InvalidRuleset)?;
+        Ok(replay)
+    }
+}
+
+/// Reads a replay from the front of its bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ParseReplayError> {
+        if self.0.len() < count {
+            return Err(ParseReplayError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ParseReplayError> {
+        Ok(self.take(N)?.try_into().expect("Should take N bytes"))
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseReplayError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, ParseReplayError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, ParseReplayError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let [byte] = self.array()?;
+            let bits = (byte & 0x7f) as u64;
+            //NOTE: the last byte only has room for the top bit
+            if bits > u64::MAX >> shift {
+                return Err(ParseReplayError::InvalidNumber);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ParseReplayError::InvalidNumber)
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseReplayError> {
+        self.varint()?
+            .try_into()
+            .map_err(|_| ParseReplayError::InvalidNumber)
+    }
+
+    fn text(&mut self) -> Result<String, ParseReplayError> {
+        let length = self.varint()?;
+        let length =
+            usize::try_from(length).map_err(|_| ParseReplayError::Truncated)?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| ParseReplayError::InvalidText)
+    }
+
+    /// Reads what follows the header.
+    fn replay(&mut self) -> Result<Replay, ParseReplayError> {
+        let engine_version = self.text()?;
+        let options = (0..self.varint()?)
+            .map(|_| self.text())
+            .collect::<Result<_, _>>()?;
+        let files = (0..self.varint()?)
+            .map(|_| Ok((self.text()?, self.text()?)))
+            .collect::<Result<_, _>>()?;
+        let handling = self
+            .text()?
+            .parse()
+            .map_err(ParseReplayError::InvalidHandling)?;
+        let seed = self.u64()?;
+
+        let count = self.varint()?;
+        let mut inputs = Vec::new();
+        let mut tick = 0u64;
+        for index in 0..count as usize {
+            let invalid = || ParseReplayError::InvalidInput { index };
+            tick = tick.checked_add(self.varint()?).ok_or_else(invalid)?;
+            let [event] = self.array()?;
+            let action = match event & 1 {
+                0 => KeyAction::Press,
+                _ => KeyAction::Release,
+            };
+            let input = input_from_code(event >> 1).ok_or_else(invalid)?;
+            inputs.push(TimedInput {
+                tick,
+                action: InputAction::new(input, action),
+            });
+        }
+
+        let summary = Summary {
+            score: self.u32()?,
+            lines: self.u32()?,
+            ticks: self.varint()?,
+            checksum: self.u64()?,
+        };
+        Ok(Replay {
+            engine_version,
+            ruleset: Ruleset { options, files },
+            handling,
+            seed,
+            inputs,
+            summary,
+        })
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_text(bytes: &mut Vec<u8>, text: &str) {
+    write_varint(bytes, text.len() as u64);
+    bytes.extend(text.as_bytes());
+}
+
+/// Inputs by their code in the file, which must never change, along with
+/// their name in scripts.
+pub const INPUT_CODES: [(Input, &str); 9] = [
+    (Input::Move(MoveKind::Left), "left"),
+    (Input::Move(MoveKind::Right), "right"),
+    (Input::Rotate(RotateKind::Clockwise), "cw"),
+    (Input::Rotate(RotateKind::CounterClockwise), "ccw"),
+    (Input::SoftDrop, "soft_drop"),
+    (Input::HardDrop, "hard_drop"),
+    (Input::Hold, "hold"),
+    (Input::CycleHold, "cycle_hold"),
+    (Input::Pause, "pause"),
+];
+
+fn input_code(input: Input) -> u8 {
+    INPUT_CODES
+        .iter()
+        .position(|(coded, _)| *coded == input)
+        .expect("Every input should have a code") as u8
+}
+
+fn input_from_code(code: u8) -> Option<Input> {
+    INPUT_CODES.get(code as usize).map(|(input, _)| *input)
+}
+
+fn input_from_name(name: &str) -> Option<Input> {
+    INPUT_CODES
+        .iter()
+        .find(|(_, named)| *named == name)
+        .map(|(input, _)| *input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::game::testing::{play_pressing, timed};
+
+    fn replay() -> Replay {
+        Replay {
+            ruleset: Ruleset {
+                options: vec![
+                    "--level=5".into(),
+                    "--big".into(),
+                    "--gravity=slow.txt".into(),
+                ],
+                files: vec![("slow.txt".into(), "0 1/120\n".into())],
+            },
+            handling: Handling {
+                das: std::time::Duration::from_millis(133),
+                ..Default::default()
+            },
+            inputs: vec![
+                timed(3, Input::Move(MoveKind::Left), KeyAction::Press),
+                timed(300, Input::Move(MoveKind::Left), KeyAction::Release),
+                timed(300, Input::HardDrop, KeyAction::Press),
+                timed(301, Input::Pause, KeyAction::Press),
+            ],
+            summary: Summary {
+                score: 1200,
+                lines: 4,
+                ticks: 1000,
+                checksum: 0xdead_beef,
+            },
+            ..Replay::new(Ruleset::default(), Handling::default(), 1234)
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = replay();
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()).ok(), Some(replay));
+    }
+
+    #[test]
+    fn varints_fill_64_bits() {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, u64::MAX);
+        assert_eq!(bytes.len(), 10);
+        assert_eq!(Reader(&bytes).varint().ok(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn saves_never_overwrite() {
+        let dir = std::env::temp_dir().join("totris-saves_never_overwrite");
+        let _ = std::fs::remove_dir_all(&dir);
+        let replay = replay();
+
+        let first = replay.save(&dir).expect("Should save the replay");
+        let second = replay.save(&dir).expect("Should save the replay");
+        assert_ne!(first, second);
+        for path in [first, second] {
+            assert_eq!(std::fs::read(path).ok(), Some(replay.to_bytes()));
+        }
+    }
+
+    #[test]
+    fn inputs_are_delta_encoded() {
+        let bytes = replay().to_bytes();
+        // 4 inputs, at 3 ticks, 297 more, as many and 1 more
+        let inputs = [4, 3, 0x00, 0xa9, 0x02, 0x01, 0, 0x0a, 1, 0x10];
+        assert!(bytes.windows(inputs.len()).any(|window| window == inputs));
+    }
+
+    #[rstest]
+    #[case(b"TOTS\x01\x00", "not a replay")]
+    #[case(b"TOTR\x02\x00", "unsupported format version 2")]
+    #[case(b"TOTR\x00\x00", "unsupported format version 0")]
+    #[case(b"TOTR\x01", "truncated replay")]
+    #[case(b"TOTR\x01\x00\x01\xff", "invalid UTF-8 text")]
+    #[case(
+        b"TOTR\x01\x00\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff",
+        "invalid number"
+    )]
+    #[case(
+        b"TOTR\x01\x00\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02",
+        "invalid number"
+    )]
+    fn invalid_headers(#[case] bytes: &[u8], #[case] expected: &str) {
+        let error = Replay::from_bytes(bytes).expect_err("Should be invalid");
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn invalid_replays() {
+        let bytes = replay().to_bytes();
+        assert!(matches!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ParseReplayError::Truncated)
+        ));
+        assert!(matches!(
+            Replay::from_bytes(&[bytes.as_slice(), &[0]].concat()),
+            Err(ParseReplayError::TrailingBytes)
+        ));
+
+        // a score past u32, then the ticks, lines and checksum again
+        let summary = replay().summary;
+        let score = bytes.len() - 8 - 2 - 1 - 2;
+        let mut overflow = bytes[..score].to_vec();
+        write_varint(&mut overflow, u32::MAX as u64 + 1);
+        write_varint(&mut overflow, summary.lines as u64);
+        write_varint(&mut overflow, summary.ticks);
+        overflow.extend(summary.checksum.to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&overflow),
+            Err(ParseReplayError::InvalidNumber)
+        ));
+
+        // the last event, before the checksum, ticks, lines and score
+        let event = bytes.len() - 8 - 2 - 1 - 2 - 1;
+        let mut bytes = bytes;
+        bytes[event] = 9 << 1;
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ParseReplayError::InvalidInput { index: 3 })
+        ));
+    }
+
+    #[test]
+    fn rulesets_are_checked() {
+        let replay = Replay {
+            ruleset: Ruleset {
+                options: vec!["--gravity=slow.txt".into()],
+                files: Vec::new(),
+            },
+            ..replay()
+        };
+        assert!(matches!(
+            Replay::from_bytes(&replay.to_bytes()),
+            Err(ParseReplayError::InvalidRuleset(RulesetError::MissingFile(
+                path
+            ))) if path == "slow.txt"
+        ));
+    }
+
+    #[test]
+    fn nested_errors_are_displayed() {
+        let replay = Replay {
+            ruleset: Ruleset {
+                options: vec!["--pieces=set.txt".into()],
+                files: vec![("set.txt".into(), "piece X\n".into())],
+            },
+            ..replay()
+        };
+        let error = Replay::from_bytes(&replay.to_bytes())
+            .expect_err("Should be invalid");
+        assert_eq!(
+            error.to_string(),
+            "invalid ruleset: invalid piece set: piece X has no cells"
+        );
+    }
+
+    #[test]
+    fn replaying_plays_the_same_game() {
+        let mut recorded =
+            Replay::new(Ruleset::default(), Handling::default(), 99);
+        let mut game = recorded.new_game().unwrap();
+        let inputs = [
+            Input::Move(MoveKind::Right),
+            Input::Rotate(RotateKind::Clockwise),
+            Input::HardDrop,
+        ];
+        play_pressing(&mut game, &inputs, 30, 0, 600);
+        recorded.record(&game);
+
+        let loaded = Replay::from_bytes(&recorded.to_bytes()).unwrap();
+        let mut replayed = loaded.new_game().unwrap();
+        for input in &loaded.inputs {
+            replayed.queue_input(*input);
+        }
+        while replayed.tick() < loaded.summary.ticks {
+            replayed.step();
+        }
+        assert_eq!(Summary::of(&replayed), loaded.summary);
+        assert_eq!(
+            replayed.engine.history_to_fumen(),
+            game.engine.history_to_fumen()
+        );
+    }
+}
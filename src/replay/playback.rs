@@ -0,0 +1,184 @@
+use crate::{
+    game::{Game, Input, KeyAction, TimedInput},
+    ruleset::RulesetError,
+};
+
+use super::Replay;
+
+/// Ticks between snapshots of the game, seeking replays the ticks from
+/// the last one before the target.
+const SNAPSHOT_INTERVAL: u64 = 600;
+
+/// Plays a replay back through the game, and seeks to any tick of it.
+pub struct Playback {
+    replay: Replay,
+    game: Game,
+    /// The game every [`SNAPSHOT_INTERVAL`] ticks, as far as it was played.
+    snapshots: Vec<Game>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Result<Self, RulesetError> {
+        let mut game = replay.new_game()?;
+        for input in &replay.inputs {
+            game.queue_input(*input);
+        }
+        Ok(Self {
+            replay,
+            snapshots: vec![game.clone()],
+            game,
+        })
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Ticks in the recorded game.
+    pub fn length(&self) -> u64 {
+        self.replay.summary.ticks
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.tick() >= self.length()
+    }
+
+    /// Plays the next tick, unless the recorded game is over.
+    pub fn step(&mut self) {
+        if self.is_over() {
+            return;
+        }
+        self.game.step();
+
+        let next_snapshot = self.snapshots.len() as u64 * SNAPSHOT_INTERVAL;
+        if self.game.tick() == next_snapshot {
+            self.snapshots.push(self.game.clone());
+        }
+    }
+
+    /// Goes to `tick`, from the closest snapshot before it when going back
+    /// or skipping ahead of it.
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.min(self.length());
+        let index =
+            ((tick / SNAPSHOT_INTERVAL) as usize).min(self.snapshots.len() - 1);
+        let snapshot = &self.snapshots[index];
+        if tick < self.game.tick() || snapshot.tick() > self.game.tick() {
+            self.game = snapshot.clone();
+        }
+        while self.game.tick() < tick {
+            self.step();
+        }
+    }
+
+    /// Inputs handled on the last tick played.
+    pub fn last_inputs(&self) -> &[TimedInput] {
+        let Some(last_tick) = self.game.tick().checked_sub(1) else {
+            return &[];
+        };
+        let inputs = &self.replay.inputs;
+        let start = inputs.partition_point(|input| input.tick < last_tick);
+        let end = inputs.partition_point(|input| input.tick <= last_tick);
+        &inputs[start..end]
+    }
+
+    /// Inputs held down after the last tick played.
+    pub fn held_inputs(&self) -> Vec<Input> {
+        let mut held = Vec::new();
+        for TimedInput { action, .. } in self
+            .replay
+            .inputs
+            .iter()
+            .take_while(|input| input.tick < self.game.tick())
+        {
+            match action.action {
+                KeyAction::Press if !held.contains(&action.input) => {
+                    held.push(action.input)
+                }
+                KeyAction::Press => {}
+                KeyAction::Release => {
+                    held.retain(|input| *input != action.input)
+                }
+            }
+        }
+        held
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        engine::{MoveKind, RotateKind},
+        game::{
+            testing::{play_pressing, timed},
+            Handling,
+        },
+        replay::Summary,
+        ruleset::Ruleset,
+    };
+
+    /// A game of pieces dropped every half second, for `ticks` ticks.
+    fn recorded(ticks: u64) -> Replay {
+        let mut replay =
+            Replay::new(Ruleset::default(), Handling::default(), 7);
+        let mut game = replay.new_game().unwrap();
+        let inputs = [
+            Input::Move(MoveKind::Left),
+            Input::Rotate(RotateKind::CounterClockwise),
+            Input::HardDrop,
+        ];
+        play_pressing(&mut game, &inputs, 30, 5, ticks);
+        replay.record(&game);
+        replay
+    }
+
+    #[test]
+    fn plays_to_the_end() {
+        let replay = recorded(1500);
+        let mut playback = Playback::new(replay.clone()).unwrap();
+        while !playback.is_over() {
+            playback.step();
+        }
+        playback.step();
+
+        assert_eq!(playback.game().tick(), 1500);
+        assert_eq!(Summary::of(playback.game()), replay.summary);
+    }
+
+    #[test]
+    fn seeking_plays_the_same_game() {
+        let mut straight = Playback::new(recorded(2000)).unwrap();
+        straight.seek(1300);
+
+        let mut seeking = Playback::new(recorded(2000)).unwrap();
+        for tick in [1900, 100, 1299, 700, 1300] {
+            seeking.seek(tick);
+            assert_eq!(seeking.game().tick(), tick);
+        }
+        assert_eq!(
+            seeking.game().engine.history_to_fumen(),
+            straight.game().engine.history_to_fumen()
+        );
+        assert_eq!(Summary::of(seeking.game()), Summary::of(straight.game()));
+
+        seeking.seek(5000);
+        assert!(seeking.is_over());
+    }
+
+    #[test]
+    fn inputs_at_the_current_tick() {
+        let mut playback = Playback::new(recorded(200)).unwrap();
+        playback.seek(61);
+        assert_eq!(playback.held_inputs(), [Input::HardDrop]);
+        assert_eq!(
+            playback.last_inputs(),
+            [timed(60, Input::HardDrop, KeyAction::Press)]
+        );
+
+        playback.seek(67);
+        assert_eq!(playback.held_inputs(), []);
+        assert_eq!(playback.last_inputs(), []);
+    }
+}
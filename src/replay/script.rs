@@ -0,0 +1,257 @@
+//! Input scripts: replays written by hand, for scripted games and tests.
+//! One entry per line, ticks counted from the start of the game:
+//!
+//! ```text
+//! # totris script
+//! seed     1234
+//! option   --level=5
+//! handling das 133
+//! input    120 press left
+//! input    126 release left
+//! end      600      # defaults to the tick after the last input
+//! ```
+//!
+//! Inputs go by their name in [`super::INPUT_CODES`]. A script says nothing
+//! of how the game ends, its summary is left empty.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    game::{InputAction, KeyAction, ParseHandlingError, TimedInput},
+    ruleset::{Ruleset, RulesetError},
+};
+
+use super::{input_from_name, Replay, Summary};
+
+/// Lines are those of the script, counted from 1, handling settings
+/// included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseScriptError {
+    Unreadable(String),
+    InvalidLine {
+        line: usize,
+    },
+    UnknownSetting {
+        line: usize,
+    },
+    /// Scripts name no files, options can only use built-in ones.
+    InvalidRuleset(RulesetError),
+    MissingSeed,
+}
+
+impl fmt::Display for ParseScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable(path) => write!(f, "cannot read {path}"),
+            Self::InvalidLine { line } => write!(f, "invalid line {line}"),
+            Self::UnknownSetting { line } => {
+                write!(f, "unknown setting on line {line}")
+            }
+            Self::InvalidRuleset(error) => {
+                write!(f, "invalid ruleset: {error}")
+            }
+            Self::MissingSeed => write!(f, "missing seed"),
+        }
+    }
+}
+
+impl std::error::Error for ParseScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidRuleset(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Replay {
+    pub fn load_script(path: &str) -> Result<Self, ParseScriptError> {
+        std::fs::read_to_string(path)
+            .map_err(|_| ParseScriptError::Unreadable(path.into()))?
+            .parse()
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ParseScriptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut seed = None;
+        let mut options = Vec::new();
+        let mut handling = String::new();
+        let mut inputs = Vec::new();
+        let mut last_tick = 0;
+        let mut end = None;
+
+        //NOTE: handling keeps a line for every line of the script, so that
+        //      its errors point at the script
+        for (index, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let setting = line.strip_prefix("handling ").unwrap_or_default();
+            handling.push_str(setting);
+            handling.push('\n');
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = ParseScriptError::InvalidLine { line: index + 1 };
+            let (entry, value) = line.split_once(' ').ok_or(invalid.clone())?;
+            let value = value.trim();
+            match entry {
+                "seed" => seed = Some(value.parse().map_err(|_| invalid)?),
+                "option" => options.push(value.to_owned()),
+                "handling" => {}
+                "input" => {
+                    let input = parse_input(value).ok_or(invalid.clone())?;
+                    let after = input.tick.checked_add(1).ok_or(invalid)?;
+                    last_tick = last_tick.max(after);
+                    inputs.push(input);
+                }
+                "end" => end = Some(value.parse().map_err(|_| invalid)?),
+                _ => return Err(invalid),
+            }
+        }
+
+        inputs.sort_by_key(|input: &TimedInput| input.tick);
+        let handling = handling.parse().map_err(|error| match error {
+            ParseHandlingError::InvalidLine { line } => {
+                ParseScriptError::InvalidLine { line: line + 1 }
+            }
+            ParseHandlingError::UnknownSetting { line } => {
+                ParseScriptError::UnknownSetting { line: line + 1 }
+            }
+            ParseHandlingError::Unreadable(_) => {
+                unreachable!("Handling is read from the script")
+            }
+        })?;
+        let seed = seed.ok_or(ParseScriptError::MissingSeed)?;
+        let replay = Self {
+            inputs,
+            summary: Summary {
+                ticks: end.unwrap_or(last_tick),
+                ..Default::default()
+            },
+            ..Replay::new(
+                Ruleset {
+                    options,
+                    ..Default::default()
+                },
+                handling,
+                seed,
+            )
+        };
+        replay
+            .new_game()
+            .map_err(ParseScriptError::InvalidRuleset)?;
+        Ok(replay)
+    }
+}
+
+fn parse_input(text: &str) -> Option<TimedInput> {
+    let mut words = text.split_whitespace();
+    let (Some(tick), Some(action), Some(input), None) =
+        (words.next(), words.next(), words.next(), words.next())
+    else {
+        return None;
+    };
+
+    let action = match action {
+        "press" => KeyAction::Press,
+        "release" => KeyAction::Release,
+        _ => return None,
+    };
+    Some(TimedInput {
+        tick: tick.parse().ok()?,
+        action: InputAction::new(input_from_name(input)?, action),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        engine::MoveKind,
+        game::{testing::timed, Handling, Input},
+        replay::INPUT_CODES,
+    };
+
+    #[test]
+    fn parse_script() {
+        let replay: Replay = "
+            # totris script
+            seed     1234
+            option   --level=5
+            handling das 133
+            input    9 release left
+            input    3 press   left   # out of order
+            input    9 press   hard_drop
+        "
+        .parse()
+        .expect("Should be a valid script");
+
+        assert_eq!(replay.seed, 1234);
+        assert_eq!(replay.ruleset.options, ["--level=5"]);
+        assert_eq!(
+            replay.handling,
+            "das 133".parse::<Handling>().expect("Should be valid")
+        );
+        let left = Input::Move(MoveKind::Left);
+        assert_eq!(
+            replay.inputs,
+            [
+                timed(3, left, KeyAction::Press),
+                timed(9, left, KeyAction::Release),
+                timed(9, Input::HardDrop, KeyAction::Press),
+            ]
+        );
+        assert_eq!(replay.summary.ticks, 10);
+        assert_eq!(
+            "seed 1\nend 600".parse::<Replay>().map(|r| r.summary.ticks),
+            Ok(600)
+        );
+    }
+
+    #[test]
+    fn every_input_has_a_name() {
+        for (input, name) in INPUT_CODES {
+            let script = format!("seed 1\ninput 0 press {name}");
+            let replay: Replay = script.parse().expect("Should be valid");
+            assert_eq!(replay.inputs[0].action.input, input);
+        }
+    }
+
+    #[test]
+    fn invalid_scripts() {
+        assert_eq!(
+            "option --big".parse::<Replay>(),
+            Err(ParseScriptError::MissingSeed)
+        );
+        assert_eq!(
+            "seed 1\ninput 3 press up".parse::<Replay>(),
+            Err(ParseScriptError::InvalidLine { line: 2 })
+        );
+        assert_eq!(
+            "seed 1\ninput 18446744073709551615 press left".parse::<Replay>(),
+            Err(ParseScriptError::InvalidLine { line: 2 })
+        );
+        assert_eq!(
+            "seed 1\n\nend soon".parse::<Replay>(),
+            Err(ParseScriptError::InvalidLine { line: 3 })
+        );
+        assert_eq!(
+            "seed 1\noption --pieces=../pieces.txt".parse::<Replay>(),
+            Err(ParseScriptError::InvalidRuleset(RulesetError::MissingFile(
+                "../pieces.txt".into()
+            )))
+        );
+        assert_eq!(
+            "seed 1\nhandling das 100\n\nhandling das fast".parse::<Replay>(),
+            Err(ParseScriptError::InvalidLine { line: 4 })
+        );
+        assert_eq!(
+            "handling das 100 # comment\nhandling turbo on".parse::<Replay>(),
+            Err(ParseScriptError::UnknownSetting { line: 2 })
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! The rules a game is played by, as given on the command line. They are
+//! kept as given, so a replay can set the same game up again. Files the
+//! options name, like piece sets or gravity tables, are only read when the
+//! ruleset is made, and their contents kept along with the options.
+
+use std::{fmt, time::Duration};
+
+use crate::{
+    engine::{
+        Engine, GravityCurve, HoldPolicy, ParseGravityCurveError,
+        ParsePieceSetError, PieceSet, SpawnRule, StackVisibility,
+    },
+    game::{Delays, Game, Handling, LockDelay, LockPolicy, TickRate},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ruleset {
+    /// Options like `--level=5`, and the fumen of the starting board.
+    pub options: Vec<String>,
+    /// Contents of the files named by the options, by path.
+    pub files: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RulesetError {
+    Unreadable(String),
+    /// A file named by an option is not part of the ruleset.
+    MissingFile(String),
+    UnknownOption(String),
+    InvalidOption(String),
+    InvalidPieceSet(ParsePieceSetError),
+    InvalidGravityCurve(ParseGravityCurveError),
+}
+
+impl fmt::Display for RulesetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreadable(path) => write!(f, "cannot read {path}"),
+            Self::MissingFile(path) => {
+                write!(f, "{path} is not in the ruleset")
+            }
+            Self::UnknownOption(option) => write!(f, "unknown option {option}"),
+            Self::InvalidOption(option) => write!(f, "invalid option {option}"),
+            Self::InvalidPieceSet(error) => {
+                write!(f, "invalid piece set: {error}")
+            }
+            Self::InvalidGravityCurve(error) => {
+                write!(f, "invalid gravity curve: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RulesetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPieceSet(error) => Some(error),
+            Self::InvalidGravityCurve(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Ruleset {
+    /// Reads the files the options name, the built-in piece sets and
+    /// gravity curves aside.
+    pub fn from_options(options: Vec<String>) -> Result<Self, RulesetError> {
+        let files = options
+            .iter()
+            .filter_map(|option| named_file(option))
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .map(|contents| (path.to_owned(), contents))
+                    .map_err(|_| RulesetError::Unreadable(path.into()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { options, files })
+    }
+
+    /// Sets a game up, without ever reading a file.
+    pub fn new_game(
+        &self,
+        seed: u64,
+        handling: Handling,
+    ) -> Result<Game, RulesetError> {
+        let mut fumen = None;
+        let mut piece_set = PieceSet::TETROMINOES;
+        let mut block_size = 1;
+        let mut visibility = StackVisibility::Visible;
+        let mut hold_policy = HoldPolicy::Standard;
+        let mut spawn_rule = SpawnRule::Guideline;
+        let mut level = 1;
+        let mut gravity_curve = GravityCurve::Guideline;
+        let mut lock_policy = LockPolicy::default();
+        let mut lock_durations = vec![(0, LockDelay::DEFAULT_DURATION)];
+        let mut delays = Delays::default();
+        let mut tick_rate = TickRate::default();
+        for arg in &self.options {
+            let invalid = || RulesetError::InvalidOption(arg.clone());
+            let millis = |text: &str| {
+                text.parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| invalid())
+            };
+            if let Some(name) = arg.strip_prefix("--pieces=") {
+                piece_set = match PieceSet::from_name(name) {
+                    Some(set) => set,
+                    None => PieceSet::interned(self.file(name)?)
+                        .map_err(RulesetError::InvalidPieceSet)?,
+                };
+            } else if let Some(text) = arg.strip_prefix("--fading=") {
+                visibility = StackVisibility::Fading(millis(text)?);
+            } else if let Some(name) = arg.strip_prefix("--hold=") {
+                hold_policy =
+                    HoldPolicy::from_name(name).ok_or_else(invalid)?;
+            } else if let Some(name) = arg.strip_prefix("--spawn=") {
+                spawn_rule = SpawnRule::from_name(name).ok_or_else(invalid)?;
+            } else if let Some(number) = arg.strip_prefix("--level=") {
+                level = number.parse().map_err(|_| invalid())?;
+            } else if let Some(name) = arg.strip_prefix("--gravity=") {
+                gravity_curve = match GravityCurve::from_name(name) {
+                    Some(curve) => curve,
+                    None => GravityCurve::interned(self.file(name)?)
+                        .map_err(RulesetError::InvalidGravityCurve)?,
+                };
+            } else if let Some(name) = arg.strip_prefix("--lock=") {
+                lock_policy =
+                    LockPolicy::from_name(name).ok_or_else(invalid)?;
+            } else if let Some(text) = arg.strip_prefix("--lock-time=") {
+                lock_durations =
+                    LockDelay::parse_durations(text).ok_or_else(invalid)?;
+            } else if let Some(number) = arg.strip_prefix("--tick-rate=") {
+                tick_rate = TickRate(
+                    number
+                        .parse()
+                        .ok()
+                        .filter(|rate| *rate > 0)
+                        .ok_or_else(invalid)?,
+                );
+            } else if let Some(text) = arg.strip_prefix("--countdown=") {
+                delays.countdown = millis(text)?;
+            } else if let Some(text) = arg.strip_prefix("--are=") {
+                delays.are = millis(text)?;
+            } else if let Some(text) = arg.strip_prefix("--line-are=") {
+                delays.line_are = millis(text)?;
+            } else if let Some(text) = arg.strip_prefix("--line-clear=") {
+                delays.line_clear = millis(text)?;
+            } else if arg == "--invisible" {
+                visibility = StackVisibility::Invisible;
+            } else if arg == "--big" {
+                block_size = 2;
+            } else if arg.starts_with("--") {
+                return Err(RulesetError::UnknownOption(arg.clone()));
+            } else {
+                fumen = Some(arg);
+            }
+        }
+
+        let engine = match fumen {
+            Some(fumen) => Engine::from_fumen(fumen)
+                .map_err(|_| RulesetError::InvalidOption(fumen.clone()))?,
+            None => Engine::new(),
+        };
+        let engine = engine
+            .with_piece_set(piece_set)
+            .with_block_size(block_size)
+            .with_hold_policy(hold_policy)
+            .with_spawn_rule(spawn_rule)
+            .with_level(level)
+            .with_gravity_curve(gravity_curve)
+            .with_stack_visibility(visibility)
+            .with_seed(seed);
+        Ok(Game::new(engine)
+            .with_tick_rate(tick_rate)
+            .with_delays(delays)
+            .with_handling(handling)
+            .with_lock_delay(LockDelay::new(lock_policy, lock_durations)))
+    }
+
+    fn file(&self, path: &str) -> Result<&str, RulesetError> {
+        self.files
+            .iter()
+            .find(|(name, _)| name == path)
+            .map(|(_, contents)| contents.as_str())
+            .ok_or_else(|| RulesetError::MissingFile(path.into()))
+    }
+}
+
+/// Path of the file an option names, if it does.
+fn named_file(option: &str) -> Option<&str> {
+    if let Some(name) = option.strip_prefix("--pieces=") {
+        return PieceSet::from_name(name).is_none().then_some(name);
+    }
+    let name = option.strip_prefix("--gravity=")?;
+    GravityCurve::from_name(name).is_none().then_some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn ruleset(options: &[&str]) -> Ruleset {
+        Ruleset {
+            options: options.iter().map(|&option| option.into()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn files_come_from_the_ruleset() {
+        let mut ruleset = ruleset(&["--pieces=/nowhere/pentominoes"]);
+        assert_eq!(
+            ruleset.new_game(0, Handling::default()).err(),
+            Some(RulesetError::MissingFile("/nowhere/pentominoes".into()))
+        );
+
+        ruleset.files.push((
+            "/nowhere/pentominoes".into(),
+            "piece X\ncells 0,0 -1,0 1,0 0,1 0,-1".into(),
+        ));
+        let mut game = ruleset
+            .new_game(0, Handling::default())
+            .expect("Should not read the file");
+        game.engine.refill_bag();
+        assert_eq!(game.engine.next_pieces()[0].0.len(), 5);
+    }
+
+    #[rstest]
+    #[case(&["--level=high"], RulesetError::InvalidOption("--level=high".into()))]
+    #[case(&["--hold=twice"], RulesetError::InvalidOption("--hold=twice".into()))]
+    #[case(&["--tick-rate=0"], RulesetError::InvalidOption("--tick-rate=0".into()))]
+    #[case(&["--turbo"], RulesetError::UnknownOption("--turbo".into()))]
+    #[case(&["not a fumen"], RulesetError::InvalidOption("not a fumen".into()))]
+    fn invalid_options(
+        #[case] options: &[&str],
+        #[case] expected: RulesetError,
+    ) {
+        assert_eq!(
+            ruleset(options).new_game(0, Handling::default()).err(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn built_in_names_are_not_files() {
+        let ruleset = Ruleset::from_options(vec![
+            "--pieces=tromino".into(),
+            "--gravity=tgm".into(),
+        ])
+        .expect("Should not read any file");
+        assert!(ruleset.files.is_empty());
+        assert_eq!(
+            Ruleset::from_options(vec!["--gravity=/nowhere/curve".into()]),
+            Err(RulesetError::Unreadable("/nowhere/curve".into()))
+        );
+    }
+}
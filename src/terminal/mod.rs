@@ -0,0 +1,624 @@
+//! Terminal frontend, behind the `tui` feature: the matrix is drawn with
+//! Unicode blocks in ANSI colors, two columns per cell so blocks look
+//! square, with the held pieces and the score on the left and the next
+//! queue on the right.
+
+use std::{
+    io::{self, Stdout, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color as TermColor, Print, ResetColor, SetForegroundColor},
+    terminal::{
+        self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+    },
+};
+
+use crate::{
+    engine::{Color, Coordinate, Matrix, MoveKind, Offset, RotateKind},
+    frontend::{
+        Command, FrontendEvent, GameLoop, InputSource, Panel, Renderer, Report,
+        VisibleCell,
+    },
+    game::{Game, Input, InputAction, KeyAction, State},
+    replay::Replay,
+};
+
+/// Frames are paced by the renderer, the terminal being drawn as fast as
+/// it can otherwise.
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+const PANEL_WIDTH: u16 = 10;
+/// Left edge of the matrix border.
+const MATRIX_LEFT: u16 = PANEL_WIDTH;
+const MATRIX_RIGHT: u16 = MATRIX_LEFT + 2 * Matrix::WIDTH as u16 + 1;
+const NEXT_LEFT: u16 = MATRIX_RIGHT + 2;
+const SCORE_TOP: u16 = 12;
+/// Rows of a piece in a panel, a line of space included.
+const SLOT_HEIGHT: u16 = 3;
+const SCREEN_WIDTH: u16 = NEXT_LEFT + PANEL_WIDTH;
+const SCREEN_HEIGHT: u16 = Matrix::HEIGHT as u16 + 2;
+
+const BORDER_COLOR: TermColor = TermColor::Grey;
+const GRID_COLOR: TermColor = TermColor::DarkGrey;
+const TEXT_COLOR: TermColor = TermColor::White;
+
+impl TryFrom<KeyCode> for Input {
+    type Error = ();
+    fn try_from(key: KeyCode) -> Result<Self, Self::Error> {
+        use Input::*;
+        Ok(match key {
+            KeyCode::Right => Move(MoveKind::Right),
+            KeyCode::Left => Move(MoveKind::Left),
+            KeyCode::Down => SoftDrop,
+            KeyCode::Char(' ') => HardDrop,
+            KeyCode::Char('z') => Rotate(RotateKind::CounterClockwise),
+            KeyCode::Char('x') => Rotate(RotateKind::Clockwise),
+            KeyCode::Char('c') => Hold,
+            KeyCode::Char('v') => CycleHold,
+            KeyCode::Esc => Pause,
+
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Plays `game` in the terminal, recording it in `replay`, which is saved
+/// when the game ends or the player quits with `q`. Reports are handed to
+/// `report` once the terminal is restored.
+pub fn run(game: Game, replay: Replay, report: impl FnMut(Report)) {
+    let terminal = RawTerminal::enter().expect("Terminal setup failed");
+    let mut renderer = TuiRenderer::new(io::stdout());
+    let mut input = TuiInput {
+        release_events: terminal.enhanced,
+        tap_keys: TapKeys::new(game.handling().das),
+    };
+
+    //NOTE: reports wait for the terminal to be restored, or they would
+    //      be written over the game and lost with the alternate screen
+    let mut reports = Vec::new();
+    GameLoop::new(game, replay)
+        .run(&mut renderer, &mut input, |report| reports.push(report));
+    drop(terminal);
+    reports.into_iter().for_each(report);
+}
+
+/// Raw mode on the alternate screen, restored when dropped, panics
+/// included.
+struct RawTerminal {
+    /// Whether the terminal reports key releases.
+    enhanced: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All)
+        )?;
+
+        //NOTE: most terminals only report presses, see `TapKeys`, and some
+        //      do not even answer the query
+        let enhanced =
+            terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+        }
+        Ok(Self { enhanced })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct TuiInput {
+    release_events: bool,
+    tap_keys: TapKeys,
+}
+
+impl InputSource for TuiInput {
+    //NOTE: events are read as they come, so they are all given no age
+    fn poll(&mut self) -> Vec<FrontendEvent> {
+        let mut events = Vec::new();
+        let mut pressed = Vec::new();
+        while event::poll(Duration::ZERO).expect("Terminal input failed") {
+            let Event::Key(key) = event::read().expect("Terminal input failed")
+            else {
+                continue;
+            };
+            if is_quit(&key) {
+                events.push(FrontendEvent::Command(Command::Quit));
+                continue;
+            }
+            let Ok(input) = Input::try_from(key.code) else {
+                continue;
+            };
+            match (self.release_events, key.kind) {
+                (true, KeyEventKind::Press) => {
+                    events.push(input_event(input, KeyAction::Press))
+                }
+                (true, KeyEventKind::Release) => {
+                    events.push(input_event(input, KeyAction::Release))
+                }
+                (true, KeyEventKind::Repeat) => {}
+                (false, _) => pressed.push(input),
+            }
+        }
+
+        if !self.release_events {
+            events.extend(
+                self.tap_keys
+                    .update(&pressed, Instant::now())
+                    .into_iter()
+                    .map(|action| FrontendEvent::Input {
+                        action,
+                        age: Duration::ZERO,
+                    }),
+            );
+        }
+        events
+    }
+}
+
+fn input_event(input: Input, action: KeyAction) -> FrontendEvent {
+    FrontendEvent::Input {
+        action: InputAction::new(input, action),
+        age: Duration::ZERO,
+    }
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    key.kind != KeyEventKind::Release
+        && match key.code {
+            KeyCode::Char('q') => true,
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            _ => false,
+        }
+}
+
+/// Stands in for release events on terminals that only report presses.
+/// A key reported once is a tap, released after half the DAS so that it
+/// shifts the piece a single column. A key the terminal repeats stays held
+/// until the repeats stop for [`TapKeys::REPEAT_GRACE`]. As terminals wait
+/// before repeating a key, holding one shifts the piece once, then again
+/// when the repeats start, and only auto shifts after that.
+struct TapKeys {
+    /// How long a key reported once is held.
+    tap_time: Duration,
+    held: Vec<TappedKey>,
+}
+
+struct TappedKey {
+    input: Input,
+    last_report: Instant,
+    /// Whether the terminal reported the key again since its press.
+    repeating: bool,
+}
+
+impl TapKeys {
+    /// Longest time between repeats of a held key that terminals commonly
+    /// use.
+    const REPEAT_GRACE: Duration = Duration::from_millis(100);
+
+    fn new(das: Duration) -> Self {
+        Self {
+            tap_time: das / 2,
+            held: Vec::new(),
+        }
+    }
+
+    /// Actions for the keys `pressed` since the last poll, made at `now`,
+    /// releases first.
+    fn update(&mut self, pressed: &[Input], now: Instant) -> Vec<InputAction> {
+        let mut actions = Vec::new();
+        self.held.retain(|key| {
+            let grace = match key.repeating {
+                true => Self::REPEAT_GRACE,
+                false => self.tap_time,
+            };
+            let held = pressed.contains(&key.input)
+                || now.saturating_duration_since(key.last_report) < grace;
+            if !held {
+                actions.push(InputAction::new(key.input, KeyAction::Release));
+            }
+            held
+        });
+
+        for &input in pressed {
+            match self.held.iter_mut().find(|key| key.input == input) {
+                Some(key) => {
+                    key.repeating |= key.last_report != now;
+                    key.last_report = now;
+                }
+                None => {
+                    actions.push(InputAction::new(input, KeyAction::Press));
+                    self.held.push(TappedKey {
+                        input,
+                        last_report: now,
+                        repeating: false,
+                    });
+                }
+            }
+        }
+        actions
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Glyph {
+    symbol: char,
+    color: TermColor,
+}
+
+impl Glyph {
+    const EMPTY: Self = Self {
+        symbol: ' ',
+        color: TermColor::Reset,
+    };
+}
+
+/// Draws into a frame buffer, only writing out what changed on present.
+struct TuiRenderer {
+    stdout: Stdout,
+    frame: Vec<Glyph>,
+    shown: Vec<Option<Glyph>>,
+    last_frame: Instant,
+}
+
+impl TuiRenderer {
+    fn new(stdout: Stdout) -> Self {
+        let size = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize;
+        Self {
+            stdout,
+            frame: vec![Glyph::EMPTY; size],
+            shown: vec![None; size],
+            last_frame: Instant::now(),
+        }
+    }
+
+    fn put(&mut self, x: u16, y: u16, symbol: char, color: TermColor) {
+        if x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+            let index = y as usize * SCREEN_WIDTH as usize + x as usize;
+            self.frame[index] = Glyph { symbol, color };
+        }
+    }
+
+    fn write(&mut self, x: u16, y: u16, text: &str, color: TermColor) {
+        for (i, symbol) in text.chars().enumerate() {
+            self.put(x + i as u16, y, symbol, color);
+        }
+    }
+
+    /// Writes `text` centered on a row of the matrix.
+    fn write_centered(&mut self, y: u16, text: &str) {
+        let width = 2 * Matrix::WIDTH as u16;
+        let length = text.chars().count() as u16;
+        let x = MATRIX_LEFT + 1 + width.saturating_sub(length) / 2;
+        self.write(x, y, text, TEXT_COLOR);
+    }
+
+    fn put_cell(&mut self, coord: Coordinate, symbol: char, color: TermColor) {
+        if let Some((x, y)) = cell_position(coord) {
+            self.put(x, y, symbol, color);
+            self.put(x + 1, y, symbol, color);
+        }
+    }
+
+    fn draw_slots(&mut self, x: u16, y: u16, pieces: &[(Vec<Offset>, Color)]) {
+        for (i, (offsets, color)) in pieces.iter().enumerate() {
+            let top = y + i as u16 * SLOT_HEIGHT;
+            if top + SLOT_HEIGHT > SCREEN_HEIGHT {
+                return;
+            }
+            let (Some(min_x), Some(max_y)) = (
+                offsets.iter().map(|offset| offset.x).min(),
+                offsets.iter().map(|offset| offset.y).max(),
+            ) else {
+                continue;
+            };
+            for offset in offsets {
+                let column = x + 1 + 2 * (offset.x - min_x) as u16;
+                let row = top + (max_y - offset.y) as u16;
+                self.put(column, row, '█', terminal_color(*color));
+                self.put(column + 1, row, '█', terminal_color(*color));
+            }
+        }
+    }
+}
+
+impl Renderer for TuiRenderer {
+    fn clear(&mut self) {
+        self.frame.fill(Glyph::EMPTY);
+        for y in 0..SCREEN_HEIGHT {
+            self.put(MATRIX_LEFT, y, '│', BORDER_COLOR);
+            self.put(MATRIX_RIGHT, y, '│', BORDER_COLOR);
+        }
+        for x in MATRIX_LEFT..=MATRIX_RIGHT {
+            self.put(x, SCREEN_HEIGHT - 1, '─', BORDER_COLOR);
+        }
+        self.put(MATRIX_LEFT, SCREEN_HEIGHT - 1, '└', BORDER_COLOR);
+        self.put(MATRIX_RIGHT, SCREEN_HEIGHT - 1, '┘', BORDER_COLOR);
+        self.write(1, 0, "HOLD", TEXT_COLOR);
+        self.write(NEXT_LEFT + 1, 0, "NEXT", TEXT_COLOR);
+    }
+
+    //NOTE: each cell is drawn on its own, big blocks only cover more
+    fn draw_matrix(&mut self, cells: &[VisibleCell], _block_size: usize) {
+        for &(coord, cell) in cells {
+            match cell {
+                Some((color, opacity)) if opacity > 0.0 => {
+                    self.put_cell(coord, faded(opacity), terminal_color(color))
+                }
+                _ => {
+                    if let Some((x, y)) = cell_position(coord) {
+                        self.put(x, y, '·', GRID_COLOR);
+                    }
+                }
+            }
+        }
+    }
+
+    //NOTE: cleared lines flash, then fade out
+    fn draw_cleared_lines(&mut self, lines: &[usize], progress: f32) {
+        for &y in lines {
+            for x in 0..Matrix::WIDTH {
+                let symbol = faded(1.0 - progress);
+                self.put_cell(Coordinate::new(x, y), symbol, TEXT_COLOR);
+            }
+        }
+    }
+
+    fn draw_piece(&mut self, cells: &[Coordinate], color: Color) {
+        for &coord in cells {
+            self.put_cell(coord, '█', terminal_color(color));
+        }
+    }
+
+    fn draw_ghost(&mut self, cells: &[Coordinate], color: Color) {
+        for &coord in cells {
+            self.put_cell(coord, '░', terminal_color(color));
+        }
+    }
+
+    //NOTE: panels are drawn top down, as many pieces as fit
+    fn draw_panel(&mut self, panel: Panel, pieces: &[(Vec<Offset>, Color)]) {
+        let (x, y) = panel_origin(panel);
+        self.draw_slots(x, y + 1, pieces);
+    }
+
+    fn draw_text(&mut self, panel: Panel, text: &str) {
+        let (x, y) = panel_origin(panel);
+        for (row, line) in text.lines().enumerate() {
+            self.write(x + 1, y + row as u16, line, TEXT_COLOR);
+        }
+    }
+
+    fn draw_state(&mut self, state: &State, progress: f32) {
+        let middle = SCREEN_HEIGHT / 2 - 1;
+        match state {
+            State::Paused(_) => {
+                for y in 0..SCREEN_HEIGHT - 1 {
+                    for x in MATRIX_LEFT + 1..MATRIX_RIGHT {
+                        self.put(x, y, ' ', TermColor::Reset);
+                    }
+                }
+                self.write_centered(middle, "PAUSED");
+            }
+            State::Countdown => {
+                let width = 2.0 * Matrix::WIDTH as f32 * (1.0 - progress);
+                let bar = "━".repeat(width.round() as usize);
+                self.write_centered(middle, &bar);
+            }
+            State::GameOver => self.write_centered(middle, "GAME OVER"),
+            _ => {}
+        }
+    }
+
+    fn present(&mut self) {
+        let mut color = None;
+        for (i, (&glyph, shown)) in
+            self.frame.iter().zip(self.shown.iter_mut()).enumerate()
+        {
+            if *shown == Some(glyph) {
+                continue;
+            }
+            let x = (i % SCREEN_WIDTH as usize) as u16;
+            let y = (i / SCREEN_WIDTH as usize) as u16;
+            if color != Some(glyph.color) {
+                queue!(self.stdout, SetForegroundColor(glyph.color))
+                    .expect("Fatal redering error");
+                color = Some(glyph.color);
+            }
+            queue!(self.stdout, MoveTo(x, y), Print(glyph.symbol))
+                .expect("Fatal redering error");
+            *shown = Some(glyph);
+        }
+        queue!(self.stdout, ResetColor).expect("Fatal redering error");
+        self.stdout.flush().expect("Fatal redering error");
+
+        if let Some(rest) = FRAME_TIME.checked_sub(self.last_frame.elapsed()) {
+            thread::sleep(rest);
+        }
+        self.last_frame = Instant::now();
+    }
+}
+
+/// Screen position of the left half of a cell, if it is on the matrix.
+fn cell_position(coord: Coordinate) -> Option<(u16, u16)> {
+    (coord.x < Matrix::WIDTH && coord.y < Matrix::HEIGHT).then(|| {
+        (
+            MATRIX_LEFT + 1 + 2 * coord.x as u16,
+            (Matrix::HEIGHT - coord.y) as u16,
+        )
+    })
+}
+
+fn panel_origin(panel: Panel) -> (u16, u16) {
+    match panel {
+        Panel::Hold => (0, 0),
+        Panel::Next => (NEXT_LEFT, 0),
+        Panel::Score => (0, SCORE_TOP),
+    }
+}
+
+/// Shade of block for the given opacity.
+fn faded(opacity: f32) -> char {
+    match opacity {
+        opacity if opacity > 0.75 => '█',
+        opacity if opacity > 0.5 => '▓',
+        opacity if opacity > 0.25 => '▒',
+        _ => '░',
+    }
+}
+
+fn terminal_color(color: Color) -> TermColor {
+    match color {
+        Color::Yellow => TermColor::Yellow,
+        Color::Cyan => TermColor::Cyan,
+        Color::Purple => TermColor::Magenta,
+        Color::Orange => TermColor::AnsiValue(208),
+        Color::Blue => TermColor::Blue,
+        Color::Green => TermColor::Green,
+        Color::Red => TermColor::Red,
+        Color::Gray => TermColor::Grey,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::{engine::Engine, game::TimedInput};
+
+    #[rstest]
+    #[case(KeyCode::Left, Some(Input::Move(MoveKind::Left)))]
+    #[case(KeyCode::Char(' '), Some(Input::HardDrop))]
+    #[case(KeyCode::Char('x'), Some(Input::Rotate(RotateKind::Clockwise)))]
+    #[case(KeyCode::Esc, Some(Input::Pause))]
+    #[case(KeyCode::Char('q'), None)]
+    fn key_mapping(#[case] key: KeyCode, #[case] input: Option<Input>) {
+        assert_eq!(Input::try_from(key).ok(), input);
+    }
+
+    #[test]
+    fn quit_keys() {
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        let c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE);
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(is_quit(&ctrl_c));
+        assert!(is_quit(&q));
+        assert!(!is_quit(&c));
+    }
+
+    #[test]
+    fn taps_are_released_before_the_das() {
+        let left = Input::Move(MoveKind::Left);
+        let mut keys = TapKeys::new(Duration::from_millis(300));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert_eq!(
+            keys.update(&[left, left], at(0)),
+            [InputAction::new(left, KeyAction::Press)]
+        );
+        assert_eq!(keys.update(&[], at(100)), []);
+        assert_eq!(
+            keys.update(&[], at(150)),
+            [InputAction::new(left, KeyAction::Release)]
+        );
+    }
+
+    #[test]
+    fn repeated_keys_are_held_until_the_repeats_stop() {
+        let left = Input::Move(MoveKind::Left);
+        let mut keys = TapKeys::new(Duration::from_millis(300));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert_eq!(
+            keys.update(&[left], at(0)),
+            [InputAction::new(left, KeyAction::Press)]
+        );
+        assert_eq!(keys.update(&[left], at(30)), []);
+        assert_eq!(keys.update(&[], at(100)), []);
+        assert_eq!(keys.update(&[left], at(120)), []);
+        assert_eq!(
+            keys.update(&[Input::HardDrop], at(220)),
+            [
+                InputAction::new(left, KeyAction::Release),
+                InputAction::new(Input::HardDrop, KeyAction::Press),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_tap_shifts_a_single_column() {
+        let mut game = Game::new(Engine::new().with_seed(1));
+        game.step();
+        let column = |game: &Game| {
+            let (cells, ..) = game.engine.cursor_info().unwrap();
+            cells.iter().map(|cell| cell.x).min().unwrap()
+        };
+        let spawned = column(&game);
+
+        let mut keys = TapKeys::new(game.handling().das);
+        let start = Instant::now();
+        let tick = game.tick_rate().tick_length();
+        for frame in 0..60 {
+            let pressed = match frame {
+                0 => vec![Input::Move(MoveKind::Left)],
+                _ => Vec::new(),
+            };
+            let now = start + tick * frame;
+            for action in keys.update(&pressed, now) {
+                game.queue_input(TimedInput {
+                    tick: game.tick(),
+                    action,
+                });
+            }
+            game.step();
+        }
+        assert_eq!(column(&game), spawned - 1);
+    }
+
+    #[test]
+    fn text_is_centered_by_its_characters() {
+        let mut renderer = TuiRenderer::new(io::stdout());
+        renderer.write_centered(0, "ÉTÉ");
+
+        //NOTE: 3 characters in 20 columns, whatever their bytes
+        let x = (MATRIX_LEFT + 1 + 8) as usize;
+        let row = &renderer.frame[x..x + 3];
+        assert_eq!(
+            row.iter().map(|glyph| glyph.symbol).collect::<String>(),
+            "ÉTÉ"
+        );
+    }
+}
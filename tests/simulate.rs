@@ -0,0 +1,82 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use totris::{
+    game::{Input, InputAction, KeyAction, TimedInput},
+    Handling, Replay, Ruleset,
+};
+
+/// Records a game dropping a piece every second, and saves it in its own
+/// file.
+fn saved_replay(name: &str, tamper: impl FnOnce(&mut Replay)) -> PathBuf {
+    let mut replay = Replay::new(Ruleset::default(), Handling::default(), 7);
+    let mut game = replay.new_game().unwrap();
+    for tick in 0..600 {
+        if tick % 60 == 0 {
+            game.queue_input(TimedInput {
+                tick,
+                action: InputAction::new(Input::HardDrop, KeyAction::Press),
+            });
+        }
+        game.step();
+    }
+    replay.record(&game);
+    tamper(&mut replay);
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, replay.to_bytes()).unwrap();
+    path
+}
+
+fn simulate(path: &Path) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_simulate"))
+        .arg(path)
+        .output()
+        .expect("Should run the simulate binary")
+}
+
+#[test]
+fn missing_arguments_print_the_usage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_simulate"))
+        .output()
+        .expect("Should run the simulate binary");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Usage"));
+}
+
+#[test]
+fn untouched_replays_pass() {
+    let output = simulate(&saved_replay("untouched.totr", |_| ()));
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn tampered_checksums_fail() {
+    let path = saved_replay("tampered.totr", |replay| {
+        replay.summary.checksum ^= 1;
+    });
+    let output = simulate(&path);
+    assert!(!output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Mismatch"));
+}
+
+#[test]
+fn invalid_replays_fail() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("invalid.totr");
+    std::fs::write(&path, b"TOTR").unwrap();
+    assert!(!simulate(&path).status.success());
+}
+
+#[test]
+fn invalid_scripts_name_their_line() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("invalid.txt");
+    std::fs::write(&path, "seed 1\nhandling das fast\n").unwrap();
+    let output = simulate(&path);
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Invalid replay: invalid line 2\n"
+    );
+}